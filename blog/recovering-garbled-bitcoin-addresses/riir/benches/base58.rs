@@ -0,0 +1,25 @@
+use base58::{FromBase58, ToBase58};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const ADDRESS: &str = "1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX71";
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let bytes = riir::base58::decode(ADDRESS).unwrap();
+
+    let mut group = c.benchmark_group("decode");
+    group.bench_function("riir", |b| {
+        b.iter(|| riir::base58::decode(black_box(ADDRESS)))
+    });
+    group.bench_function("base58", |b| b.iter(|| black_box(ADDRESS).from_base58()));
+    group.finish();
+
+    let mut group = c.benchmark_group("encode");
+    group.bench_function("riir", |b| {
+        b.iter(|| riir::base58::encode(black_box(&bytes)))
+    });
+    group.bench_function("base58", |b| b.iter(|| black_box(&bytes[..]).to_base58()));
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
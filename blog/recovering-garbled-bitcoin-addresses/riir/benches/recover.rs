@@ -0,0 +1,72 @@
+// Run with RUSTFLAGS="-C target-feature=+avx2,+sha", otherwise the SIMD backends are crippled.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use riir::recover::{recover_with, Pins};
+use riir::sha256::{Backend, Scalar, ShaNi, Simd};
+
+const ADDRESS: &str = "1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX71";
+
+// Lowercases the address, but pins all but the last `count` letters that are valid base58
+// characters in both cases, so that there are 2^count candidates
+fn garble(count: usize) -> (String, Pins) {
+    let ambiguous_positions: Vec<usize> = ADDRESS
+        .bytes()
+        .enumerate()
+        .filter(|&(_, byte)| {
+            byte.is_ascii_alphabetic()
+                && riir::base58::digit(byte.to_ascii_uppercase()).is_some()
+                && riir::base58::digit(byte.to_ascii_lowercase()).is_some()
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let pins = ambiguous_positions[..ambiguous_positions.len() - count]
+        .iter()
+        .map(|&i| (i, ADDRESS.as_bytes()[i]))
+        .collect();
+    (ADDRESS.to_lowercase(), pins)
+}
+
+fn bench_backend<B: Backend>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("hashes");
+    let messages: Vec<[u8; 21]> = (0..1024u32)
+        .map(|i| {
+            let mut message = [0u8; 21];
+            message[17..].copy_from_slice(&i.to_be_bytes());
+            message
+        })
+        .collect();
+    group.throughput(Throughput::Elements(messages.len() as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for chunk in black_box(&messages).chunks(B::PARALLELISM) {
+                black_box(B::checksums(chunk, true));
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("candidates");
+    group.sample_size(10);
+    // The address has 23 ambiguous letters in total
+    for ambiguous_letters in [8, 12, 16, 20, 23] {
+        let input = garble(ambiguous_letters);
+        group.throughput(Throughput::Elements(1 << ambiguous_letters));
+        group.bench_with_input(
+            BenchmarkId::new(name, ambiguous_letters),
+            &input,
+            |b, (garbled_address, pins)| {
+                b.iter(|| recover_with::<B>(garbled_address, pins).unwrap())
+            },
+        );
+    }
+    group.finish();
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    bench_backend::<Scalar>(c, "scalar");
+    bench_backend::<Simd>(c, "simd");
+    bench_backend::<ShaNi>(c, "sha-ni");
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
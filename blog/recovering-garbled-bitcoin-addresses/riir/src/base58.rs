@@ -0,0 +1,99 @@
+// Base58 as used by Bitcoin, limited to payloads of at most 25 bytes so that the whole number fits
+// in a u200. Each leading zero byte is encoded as a separate '1' character.
+
+use crate::u200::u200;
+use std::fmt;
+
+pub const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+// Maps ASCII bytes to digits, with 0xff standing for characters outside the alphabet
+const DIGITS: [u8; 256] = {
+    let mut digits = [0xff; 256];
+    let mut i = 0;
+    while i < ALPHABET.len() {
+        digits[ALPHABET[i] as usize] = i as u8;
+        i += 1;
+    }
+    digits
+};
+
+// The longest payload representable
+pub const MAX_BYTES: usize = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidCharacter { index: usize, byte: u8 },
+    TooLong,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidCharacter { index, byte } => write!(
+                f,
+                "invalid base58 character {:?} at position {index}",
+                *byte as char
+            ),
+            DecodeError::TooLong => write!(f, "the decoded data is longer than {MAX_BYTES} bytes"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub fn digit(byte: u8) -> Option<u8> {
+    match DIGITS[byte as usize] {
+        0xff => None,
+        digit => Some(digit),
+    }
+}
+
+// Returns the count of leading '1' characters, i.e. zero bytes, and the number encoded by the rest
+// of the string
+pub fn decode_number(string: &str) -> Result<(usize, u200), DecodeError> {
+    let leading_zeros = string.bytes().take_while(|&byte| byte == b'1').count();
+    let mut number = u200::ZERO;
+    for (index, byte) in string.bytes().enumerate().skip(leading_zeros) {
+        let digit = digit(byte).ok_or(DecodeError::InvalidCharacter { index, byte })?;
+        number = number
+            .checked_mul(58)
+            .and_then(|number| number.checked_add((digit as u64).into()))
+            .ok_or(DecodeError::TooLong)?;
+    }
+    Ok((leading_zeros, number))
+}
+
+pub fn decode(string: &str) -> Result<Vec<u8>, DecodeError> {
+    let (leading_zeros, number) = decode_number(string)?;
+    let bytes = number.to_be_bytes();
+    let significant_bytes = &bytes[bytes.iter().take_while(|&&byte| byte == 0).count()..];
+    if leading_zeros + significant_bytes.len() > MAX_BYTES {
+        return Err(DecodeError::TooLong);
+    }
+    let mut decoded = vec![0u8; leading_zeros];
+    decoded.extend_from_slice(significant_bytes);
+    Ok(decoded)
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    assert!(
+        bytes.len() <= MAX_BYTES,
+        "Cannot encode more than {MAX_BYTES} bytes"
+    );
+    let leading_zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+
+    let mut padded_bytes = [0u8; MAX_BYTES];
+    padded_bytes[MAX_BYTES - bytes.len()..].copy_from_slice(bytes);
+    let mut number = u200::from_be_bytes(padded_bytes);
+
+    // Digits are produced from least significant to most significant
+    let mut encoded = Vec::with_capacity(35);
+    while !number.is_zero() {
+        let (quotient, remainder) = number.div_rem(58);
+        encoded.push(ALPHABET[remainder as usize]);
+        number = quotient;
+    }
+    encoded.resize(encoded.len() + leading_zeros, b'1');
+    encoded.reverse();
+    String::from_utf8(encoded).unwrap()
+}
@@ -0,0 +1,34 @@
+use riir::bip39::recover;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(wordlist_path) = args.next() else {
+        eprintln!("Usage: bip39 <wordlist> <word>..., use ? for unknown words");
+        std::process::exit(1);
+    };
+    let words: Vec<String> = args.map(|word| word.to_lowercase()).collect();
+
+    let wordlist: Vec<String> = match std::fs::read_to_string(&wordlist_path) {
+        Ok(wordlist) => wordlist
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect(),
+        Err(error) => {
+            eprintln!("Failed to read {wordlist_path}: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    match recover(&words, &wordlist) {
+        Ok(mnemonics) => {
+            for mnemonic in mnemonics {
+                eprintln!("Found valid mnemonic: {mnemonic}");
+            }
+        }
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1);
+        }
+    }
+}
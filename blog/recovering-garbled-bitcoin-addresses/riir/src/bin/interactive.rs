@@ -0,0 +1,173 @@
+use riir::recover::{narrow, recover, Pins};
+use std::io::Write;
+
+// ANSI escape sequences
+const CHANGED: &str = "\x1b[1;33m";
+const PINNED: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+const HELP: &str = "Commands:
+  pin <position> <character>   the character at this position is known to be correct
+  unpin <position>             forget what is known about this position
+  pick <candidate>             print the candidate and exit
+  help                         show this message
+  quit                         exit without picking";
+
+fn print_candidates(garbled_address: &str, pins: &Pins, candidates: &[String]) {
+    let indent = " ".repeat(8);
+
+    let tens: String = (0..garbled_address.len())
+        .map(|i| {
+            if i % 10 == 0 {
+                char::from(b'0' + (i / 10) as u8)
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    let ones: String = (0..garbled_address.len())
+        .map(|i| char::from(b'0' + (i % 10) as u8))
+        .collect();
+    println!("{indent}{}", tens.trim_end());
+    println!("{indent}{ones}");
+
+    let garbled_line: String = garbled_address
+        .char_indices()
+        .map(|(i, c)| {
+            if let Some(&pinned_byte) = pins.get(&i) {
+                format!("{PINNED}{}{RESET}", pinned_byte as char)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect();
+    println!("garbled {garbled_line}");
+
+    for (candidate_index, candidate) in candidates.iter().enumerate() {
+        let line: String = candidate
+            .bytes()
+            .zip(garbled_address.bytes())
+            .map(|(byte, garbled_byte)| {
+                if byte != garbled_byte {
+                    format!("{CHANGED}{}{RESET}", byte as char)
+                } else {
+                    (byte as char).to_string()
+                }
+            })
+            .collect();
+        println!("{:>7} {line}", format!("[{}]", candidate_index + 1));
+    }
+
+    // Point at the positions the candidates disagree on, as those are worth pinning
+    if let Some(first) = candidates.first() {
+        let disagreements: String = first
+            .bytes()
+            .enumerate()
+            .map(|(i, byte)| {
+                if candidates
+                    .iter()
+                    .any(|candidate| candidate.as_bytes()[i] != byte)
+                {
+                    '^'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        if !disagreements.trim().is_empty() {
+            println!("{indent}{}", disagreements.trim_end());
+        }
+    }
+}
+
+fn main() {
+    let Some(garbled_address) = std::env::args().nth(1) else {
+        eprintln!("Usage: interactive <garbled address>");
+        std::process::exit(1);
+    };
+
+    let mut pins = Pins::new();
+    let mut candidates = match recover(&garbled_address, &pins) {
+        Ok(candidates) => candidates,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut lines = std::io::stdin().lines();
+    loop {
+        println!();
+        match candidates.len() {
+            0 => println!("No valid addresses match"),
+            1 => println!("1 valid address matches"),
+            count => println!("{count} valid addresses match"),
+        }
+        print_candidates(&garbled_address, &pins, &candidates);
+
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+        let Some(line) = lines.next() else {
+            return;
+        };
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                eprintln!("Failed to read stdin: {error}");
+                std::process::exit(1);
+            }
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let mut new_pins = pins.clone();
+        // Pins only rule candidates out, so a new pin just filters the current candidates, while
+        // forgetting or changing one needs a new search
+        let new_candidates = match words[..] {
+            ["pin", position, character] => {
+                let (Ok(position), [byte]) = (position.parse(), character.as_bytes()) else {
+                    println!("Usage: pin <position> <character>");
+                    continue;
+                };
+                match new_pins.insert(position, *byte) {
+                    Some(previous) if previous != *byte => recover(&garbled_address, &new_pins),
+                    _ => narrow(&garbled_address, &candidates, &new_pins),
+                }
+            }
+            ["unpin", position] => {
+                let Ok(position) = position.parse() else {
+                    println!("Usage: unpin <position>");
+                    continue;
+                };
+                new_pins.remove(&position);
+                recover(&garbled_address, &new_pins)
+            }
+            ["pick", candidate_index] => {
+                match candidate_index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| candidates.get(i.checked_sub(1)?))
+                {
+                    Some(candidate) => {
+                        println!("{candidate}");
+                        return;
+                    }
+                    None => println!("No such candidate"),
+                }
+                continue;
+            }
+            ["quit"] => return,
+            _ => {
+                println!("{HELP}");
+                continue;
+            }
+        };
+
+        match new_candidates {
+            Ok(new_candidates) => {
+                pins = new_pins;
+                candidates = new_candidates;
+            }
+            Err(error) => println!("Error: {error}"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// BIP39 mnemonic recovery: given a mnemonic with misspelled or unknown words, finds all mnemonics
+// with a valid checksum that it could have been.
+
+use crate::sha256::ParallelChecksum;
+use itertools::Itertools;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub const WORDLIST_LEN: usize = 2048;
+
+// Misspelled words are replaced with every word from the list within this Levenshtein distance
+const MAX_EDIT_DISTANCE: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidWordlist { length: usize },
+    InvalidWordCount { count: usize },
+    NoSimilarWords { word: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidWordlist { length } => write!(
+                f,
+                "the wordlist must contain {WORDLIST_LEN} words, not {length}"
+            ),
+            Error::InvalidWordCount { count } => write!(
+                f,
+                "the mnemonic must consist of 12, 15, 18, 21 or 24 words, not {count}"
+            ),
+            Error::NoSimilarWords { word } => {
+                write!(f, "no words in the list are similar to {word}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+    let mut current_row = vec![0; b.len() + 1];
+    for (i, &byte_a) in a.iter().enumerate() {
+        current_row[0] = i + 1;
+        for (j, &byte_b) in b.iter().enumerate() {
+            current_row[j + 1] = (previous_row[j] + (byte_a != byte_b) as usize)
+                .min(previous_row[j + 1] + 1)
+                .min(current_row[j] + 1);
+        }
+        std::mem::swap(&mut previous_row, &mut current_row);
+    }
+    previous_row[b.len()]
+}
+
+// Indices of the words the given one may stand for. "?" is an unknown word.
+pub fn candidates_for_word(word: &str, wordlist: &[String]) -> Result<Vec<u16>, Error> {
+    if word == "?" {
+        // Unknown word, anything goes
+        return Ok((0..wordlist.len() as u16).collect());
+    }
+    if let Some(index) = wordlist.iter().position(|w| w == word) {
+        // Assume words from the list are spelled correctly
+        return Ok(vec![index as u16]);
+    }
+    let candidates: Vec<u16> = (0..wordlist.len() as u16)
+        .filter(|&index| {
+            edit_distance(word.as_bytes(), wordlist[index as usize].as_bytes()) <= MAX_EDIT_DISTANCE
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Err(Error::NoSimilarWords {
+            word: word.to_string(),
+        });
+    }
+    Ok(candidates)
+}
+
+// Packs 11-bit word indices into the N entropy bytes. The last word only contributes its leading
+// bits; the rest is the checksum.
+pub fn pack_entropy<const N: usize>(leading_words: &[u16], last_word_entropy: u16) -> [u8; N] {
+    let checksum_bits = N * 8 / 32;
+    let mut entropy = [0u8; N];
+    let mut accumulator: u32 = 0;
+    let mut accumulated_bits = 0;
+    let mut output_index = 0;
+    let words = leading_words
+        .iter()
+        .map(|&index| (index, 11))
+        .chain([(last_word_entropy, 11 - checksum_bits)]);
+    for (bits, count) in words {
+        accumulator = (accumulator << count) | bits as u32;
+        accumulated_bits += count;
+        while accumulated_bits >= 8 {
+            accumulated_bits -= 8;
+            entropy[output_index] = (accumulator >> accumulated_bits) as u8;
+            output_index += 1;
+        }
+    }
+    entropy
+}
+
+// N is the length of the entropy in bytes. Returns the word indices of the valid mnemonics.
+fn search<const N: usize>(candidates: &[Vec<u16>]) -> Vec<Vec<u16>> {
+    let checksum_bits = N * 8 / 32;
+    let (last_word_candidates, leading_words_candidates) = candidates.split_last().unwrap();
+
+    // Words that differ only in checksum bits share the hash, so group them by the entropy bits
+    let mut last_word_by_entropy: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    for &index in last_word_candidates {
+        last_word_by_entropy
+            .entry(index >> checksum_bits)
+            .or_default()
+            .push(index & ((1 << checksum_bits) - 1));
+    }
+
+    let found = RefCell::new(Vec::new());
+    let mut parallel_checksum = ParallelChecksum::<N>::single();
+
+    for leading_words in leading_words_candidates
+        .iter()
+        .map(|words| words.iter().copied())
+        .multi_cartesian_product()
+    {
+        for (&last_word_entropy, last_word_checksums) in &last_word_by_entropy {
+            let entropy = pack_entropy::<N>(&leading_words, last_word_entropy);
+            let leading_words = leading_words.clone();
+            let found = &found;
+            parallel_checksum.compute_later(
+                entropy,
+                Box::new(move |correct_checksum| {
+                    let correct_checksum = (correct_checksum >> (32 - checksum_bits)) as u16;
+                    if !last_word_checksums.contains(&correct_checksum) {
+                        // Checksum failed
+                        return;
+                    }
+                    let last_word = (last_word_entropy << checksum_bits) | correct_checksum;
+                    let mut mnemonic = leading_words;
+                    mnemonic.push(last_word);
+                    found.borrow_mut().push(mnemonic);
+                }),
+            );
+        }
+    }
+
+    parallel_checksum.finalize();
+    let mut found = found.take();
+    found.sort();
+    found
+}
+
+// Returns all mnemonics with a valid checksum that the words may stand for, see
+// `candidates_for_word`
+pub fn recover(words: &[String], wordlist: &[String]) -> Result<Vec<String>, Error> {
+    if wordlist.len() != WORDLIST_LEN {
+        return Err(Error::InvalidWordlist {
+            length: wordlist.len(),
+        });
+    }
+    let candidates: Vec<Vec<u16>> = words
+        .iter()
+        .map(|word| candidates_for_word(word, wordlist))
+        .collect::<Result<_, _>>()?;
+    let found = match words.len() {
+        12 => search::<16>(&candidates),
+        15 => search::<20>(&candidates),
+        18 => search::<24>(&candidates),
+        21 => search::<28>(&candidates),
+        24 => search::<32>(&candidates),
+        count => return Err(Error::InvalidWordCount { count }),
+    };
+    Ok(found
+        .iter()
+        .map(|mnemonic| {
+            mnemonic
+                .iter()
+                .map(|&index| &wordlist[index as usize])
+                .join(" ")
+        })
+        .collect())
+}
@@ -0,0 +1,7 @@
+#![feature(bigint_helper_methods, portable_simd, slice_as_chunks)]
+
+pub mod base58;
+pub mod bip39;
+pub mod recover;
+pub mod sha256;
+pub mod u200;
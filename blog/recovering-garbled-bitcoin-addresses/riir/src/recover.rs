@@ -0,0 +1,413 @@
+// The search from attempt9, packaged for reuse: given an address with lost case, finds all
+// addresses with valid checksums that match it case-insensitively.
+
+use crate::base58::{self, ALPHABET};
+use crate::sha256::{Backend, ParallelChecksum, ShaNi};
+use crate::u200::u200;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidCharacter {
+        index: usize,
+        byte: u8,
+    },
+    InvalidPin {
+        index: usize,
+        byte: u8,
+    },
+    PinOutOfRange {
+        index: usize,
+        length: usize,
+    },
+    TooManyLeadingOnes {
+        count: usize,
+    },
+    InvalidLength {
+        length: usize,
+        leading_ones: usize,
+        min_length: usize,
+        max_length: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidCharacter { index, byte } => write!(
+                f,
+                "character {:?} at position {index} is not valid in any case",
+                *byte as char
+            ),
+            Error::InvalidPin { index, byte } => write!(
+                f,
+                "cannot pin position {index} to {:?}, which does not match the address",
+                *byte as char
+            ),
+            Error::PinOutOfRange { index, length } => write!(
+                f,
+                "cannot pin position {index}, the address only has {length} characters"
+            ),
+            Error::TooManyLeadingOnes { count } => write!(
+                f,
+                "the address starts with {count} '1' characters, but a payload only has \
+                 {PAYLOAD_BYTES} bytes"
+            ),
+            Error::InvalidLength {
+                length,
+                leading_ones,
+                min_length,
+                max_length,
+            } => write!(
+                f,
+                "an address starting with {leading_ones} '1' characters must be \
+                 {min_length}-{max_length} characters long, not {length}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// Positions whose case is known for sure, mapped to the correct characters
+pub type Pins = BTreeMap<usize, u8>;
+
+// Version byte, RIPEMD-160 hash, checksum
+const PAYLOAD_BYTES: usize = 25;
+
+// Each leading '1' stands for a zero byte of the payload. The rest of the address encodes a number
+// that takes exactly the remaining bytes, without leading zeros. Returns the inclusive range of
+// such numbers.
+fn number_range(leading_ones: usize) -> (u200, u200) {
+    let significant_bytes = PAYLOAD_BYTES - leading_ones;
+    if significant_bytes == 0 {
+        return (u200::ZERO, u200::ZERO);
+    }
+    let mut min_bytes = [0u8; PAYLOAD_BYTES];
+    min_bytes[leading_ones] = 1;
+    let mut max_bytes = [0u8; PAYLOAD_BYTES];
+    max_bytes[leading_ones..].fill(0xff);
+    (
+        u200::from_be_bytes(min_bytes),
+        u200::from_be_bytes(max_bytes),
+    )
+}
+
+// Returns the inclusive range of address lengths that can encode a payload with the given count of
+// leading zero bytes
+fn length_range(leading_ones: usize) -> (usize, usize) {
+    let (min_number, max_number) = number_range(leading_ones);
+    let mut lengths = Vec::new();
+    if min_number.is_zero() {
+        lengths.push(leading_ones);
+    }
+    // n digits, the first of which is not '1', encode numbers in [58^(n - 1), 58^n - 1]
+    let mut power_58_n_minus_1: u200 = 1.into();
+    for n in 1.. {
+        if power_58_n_minus_1 > max_number {
+            break;
+        }
+        let power_58_n = power_58_n_minus_1.checked_mul(58);
+        // The largest n-digit number, 58^n - 1, has to be at least min_number
+        if power_58_n.map_or(true, |power_58_n| power_58_n > min_number) {
+            lengths.push(leading_ones + n);
+        }
+        let Some(power_58_n) = power_58_n else {
+            break;
+        };
+        power_58_n_minus_1 = power_58_n;
+    }
+    (lengths[0], *lengths.last().unwrap())
+}
+
+struct Search {
+    // The smallest number matching the garbled address, or None if even it doesn't fit in u200
+    parsed_number: Option<u200>,
+    // The range of numbers that encode a payload of the right length
+    min_number: u200,
+    max_number: u200,
+    // Characters that can only be uppercase are in uppercase, ambiguous ones too
+    base_address: Vec<u8>,
+    // Alternatives for ambiguous characters: digit index and how much switching the character to
+    // lowercase adds to the number, from most significant to least significant
+    possible_differences: Vec<(usize, u200)>,
+    found: RefCell<Vec<String>>,
+}
+
+// Pins have to be inside the address and only choose the case of a character that is valid in
+// that case
+fn check_pins(garbled_address: &str, pins: &Pins) -> Result<(), Error> {
+    for (&index, &byte) in pins {
+        let Some(garbled_byte) = garbled_address.as_bytes().get(index) else {
+            return Err(Error::PinOutOfRange {
+                index,
+                length: garbled_address.len(),
+            });
+        };
+        if !byte.eq_ignore_ascii_case(garbled_byte) || base58::digit(byte).is_none() {
+            return Err(Error::InvalidPin { index, byte });
+        }
+    }
+    Ok(())
+}
+
+fn parse(garbled_address: &str, pins: &Pins) -> Result<Search, Error> {
+    check_pins(garbled_address, pins)?;
+    let leading_ones = garbled_address
+        .bytes()
+        .take_while(|&byte| byte == b'1')
+        .count();
+    if leading_ones > PAYLOAD_BYTES {
+        return Err(Error::TooManyLeadingOnes {
+            count: leading_ones,
+        });
+    }
+    let (min_length, max_length) = length_range(leading_ones);
+    if !(min_length..=max_length).contains(&garbled_address.len()) {
+        return Err(Error::InvalidLength {
+            length: garbled_address.len(),
+            leading_ones,
+            min_length,
+            max_length,
+        });
+    }
+    let (min_number, max_number) = number_range(leading_ones);
+
+    let mut parsed_number: Option<u200> = Some(0.into());
+    let mut power_58_i: u200 = 1.into();
+    let mut possible_differences = Vec::new();
+    let mut base_address = vec![0u8; garbled_address.len()];
+
+    for (digit_index, byte) in garbled_address.bytes().enumerate().rev() {
+        // Some letters, like L and o, are only valid base58 characters in one case; this
+        // complicates the code a bit
+        let mut digit1 = base58::digit(byte.to_ascii_uppercase());
+        let mut digit2 = base58::digit(byte.to_ascii_lowercase());
+        if let Some(&pinned_byte) = pins.get(&digit_index) {
+            // Only the pinned variant is right
+            if pinned_byte.is_ascii_uppercase() {
+                digit2 = None;
+            } else {
+                digit1 = None;
+            }
+        }
+        // The numbers may overflow for 35-character addresses without leading ones. Such
+        // candidates are certainly too long, so we track this rather than fail.
+        let mut add_digit = |digit: u8| {
+            parsed_number = parsed_number
+                .zip(power_58_i.checked_mul(digit as u64))
+                .and_then(|(number, addend)| number.checked_add(addend));
+        };
+        match (digit1, digit2) {
+            (Some(digit1), Some(digit2)) if digit1 != digit2 => {
+                // Two distinct variants are possible
+                add_digit(digit1);
+                base_address[digit_index] = byte.to_ascii_uppercase();
+                // digit1 is uppercase, digit2 is lowercase, lowercase comes after uppercase in the
+                // alphabet, so the difference is positive
+                let fixup = power_58_i.checked_mul((digit2 - digit1) as u64);
+                possible_differences.push((digit_index, fixup.unwrap_or(u200::MAX)));
+            }
+            (Some(digit), _) => {
+                // Just the first variant is right
+                add_digit(digit);
+                base_address[digit_index] = ALPHABET[digit as usize];
+            }
+            (_, Some(digit)) => {
+                // Just the second variant is right
+                add_digit(digit);
+                base_address[digit_index] = ALPHABET[digit as usize];
+            }
+            (None, None) => {
+                return Err(Error::InvalidCharacter {
+                    index: digit_index,
+                    byte,
+                })
+            }
+        }
+        power_58_i = power_58_i.checked_mul(58).unwrap_or(u200::MAX);
+    }
+
+    // Recurse from left to right, i.e. from most significant to least significant
+    possible_differences.reverse();
+
+    Ok(Search {
+        parsed_number,
+        min_number,
+        max_number,
+        base_address,
+        possible_differences,
+        found: RefCell::new(Vec::new()),
+    })
+}
+
+impl Search {
+    fn iterate_through_possible_differences<'a, B: Backend>(
+        &'a self,
+        parallel_checksum: &mut ParallelChecksum<'a, 21, B>,
+        partly_fixed_number: u200,
+        possible_differences_tail: &'a [(usize, u200)],
+        suffix_sums_of_possible_differences_tail: &[u200],
+        mut subset_bitmask: u64,
+    ) {
+        let interval_length = suffix_sums_of_possible_differences_tail[0];
+        // Regardless of our further choices, the final fixed_number will be in range
+        // [partly_fixed_number, partly_fixed_number + interval_length]. The end of the interval
+        // is None if it doesn't fit in u200.
+        let interval_end = partly_fixed_number.checked_add(interval_length);
+
+        // Does the interval contain any numbers of the right length?
+        if partly_fixed_number > self.max_number
+            || interval_end.is_some_and(|interval_end| interval_end < self.min_number)
+        {
+            return;
+        }
+
+        // Do all the numbers in this range have identical leading 21 bytes?
+        let leading_bytes_mask = !u200::from((1 << 32) - 1);
+        if interval_end.map(|interval_end| interval_end & leading_bytes_mask)
+            != Some(partly_fixed_number & leading_bytes_mask)
+        {
+            // No, they don't. This means we have to guess whether to add the current fixup.
+            let (digit_index, fixup_to_add) = &possible_differences_tail[0];
+
+            // Yes
+            if let Some(number) = partly_fixed_number.checked_add(*fixup_to_add) {
+                self.iterate_through_possible_differences(
+                    parallel_checksum,
+                    number,
+                    &possible_differences_tail[1..],
+                    &suffix_sums_of_possible_differences_tail[1..],
+                    subset_bitmask | (1 << digit_index),
+                );
+            }
+
+            // No
+            self.iterate_through_possible_differences(
+                parallel_checksum,
+                partly_fixed_number,
+                &possible_differences_tail[1..],
+                &suffix_sums_of_possible_differences_tail[1..],
+                subset_bitmask,
+            );
+
+            return;
+        }
+
+        // Yes, they are the same. Let's compute the expected checksum.
+
+        // Convert the number to a byte string
+        let byte_string_25 = partly_fixed_number.to_be_bytes();
+
+        let mut current_checksum =
+            u32::from_be_bytes(*byte_string_25[21..].first_chunk::<4>().unwrap());
+
+        parallel_checksum.compute_later(
+            *byte_string_25.first_chunk::<21>().unwrap(),
+            Box::new(move |correct_checksum| {
+                if current_checksum > correct_checksum {
+                    // The minimal possible value is greater than what we need. Therefore, there is
+                    // no solution.
+                    return;
+                }
+
+                // Determine which fixups to add to obtain current_checksum == correct_checksum
+                for (digit_index, fixup_to_add) in possible_differences_tail {
+                    // fixup_to_add necessarily fits in u32
+                    let fixup_to_add = fixup_to_add.0 as u32;
+                    if current_checksum + fixup_to_add <= correct_checksum {
+                        subset_bitmask |= 1 << digit_index;
+                        current_checksum += fixup_to_add;
+                    }
+                }
+
+                if current_checksum != correct_checksum {
+                    // No solution
+                    return;
+                }
+
+                // The checksum is right, but the number might still have the wrong length
+                let fixed_number = (partly_fixed_number & leading_bytes_mask)
+                    + u200::from(current_checksum as u64);
+                if !(self.min_number..=self.max_number).contains(&fixed_number) {
+                    return;
+                }
+
+                // This must be a valid address. Let's recover the string.
+                let mut address = self.base_address.clone();
+                for &(digit_index, _) in &self.possible_differences {
+                    if (subset_bitmask >> digit_index) & 1 == 1 {
+                        // This character was converted to lowercase
+                        address[digit_index] = address[digit_index].to_ascii_lowercase();
+                    }
+                }
+                self.found
+                    .borrow_mut()
+                    .push(String::from_utf8(address).unwrap());
+            }),
+        );
+    }
+}
+
+// Returns all addresses with a valid checksum that match the garbled address case-insensitively
+// and agree with the pins
+pub fn recover(garbled_address: &str, pins: &Pins) -> Result<Vec<String>, Error> {
+    recover_with::<ShaNi>(garbled_address, pins)
+}
+
+// Same as recover, but with a particular SHA-256 backend
+pub fn recover_with<B: Backend>(garbled_address: &str, pins: &Pins) -> Result<Vec<String>, Error> {
+    let search = parse(garbled_address, pins)?;
+    let Some(parsed_number) = search.parsed_number else {
+        // Even the smallest candidate is too large
+        return Ok(Vec::new());
+    };
+
+    // For differences A, B, C, computes [A + B + C, B + C, C, 0]. Sums that don't fit in u200 are
+    // replaced with the maximum, as the search only needs to know they are too large.
+    let mut suffix_sums_of_possible_differences: Vec<u200> = search
+        .possible_differences
+        .iter()
+        .rev()
+        .scan(0.into(), |state, (_, fixup)| {
+            *state = u200::checked_add(*state, *fixup).unwrap_or(u200::MAX);
+            Some(*state)
+        })
+        .collect();
+    suffix_sums_of_possible_differences.reverse();
+    suffix_sums_of_possible_differences.push(0.into());
+
+    let mut parallel_checksum = ParallelChecksum::<21, B>::double();
+    search.iterate_through_possible_differences(
+        &mut parallel_checksum,
+        parsed_number,
+        &search.possible_differences,
+        &suffix_sums_of_possible_differences,
+        0,
+    );
+    parallel_checksum.finalize();
+
+    let mut found = search.found.take();
+    found.sort();
+    Ok(found)
+}
+
+// Keeps the candidates found by an earlier search that agree with the pins. Pins only rule
+// addresses out, so after adding pins this gives the same result as searching again.
+pub fn narrow(
+    garbled_address: &str,
+    candidates: &[String],
+    pins: &Pins,
+) -> Result<Vec<String>, Error> {
+    check_pins(garbled_address, pins)?;
+    Ok(candidates
+        .iter()
+        .filter(|candidate| {
+            pins.iter()
+                .all(|(&index, &byte)| candidate.as_bytes()[index] == byte)
+        })
+        .cloned()
+        .collect())
+}
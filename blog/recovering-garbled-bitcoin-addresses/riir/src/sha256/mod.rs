@@ -0,0 +1,94 @@
+// SHA-256 of short messages, as needed for checksums. There are several backends, matching the
+// attempts from the post, so that they can be benchmarked against each other.
+
+use arrayvec::ArrayVec;
+
+pub mod scalar;
+pub mod sha_ni;
+pub mod simd;
+
+pub use scalar::Scalar;
+pub use sha_ni::ShaNi;
+pub use simd::Simd;
+
+// The largest PARALLELISM among the backends
+pub const MAX_PARALLELISM: usize = 8;
+
+pub trait Backend {
+    // How many messages are hashed at once
+    const PARALLELISM: usize;
+
+    // Computes the leading four bytes of SHA-256 (or SHA-256 applied twice) of each input as a
+    // big-endian number. At most PARALLELISM inputs are passed.
+    fn checksums<const N: usize>(inputs: &[[u8; N]], double: bool) -> [u32; MAX_PARALLELISM];
+}
+
+// Pads a message that fits in one 512-bit chunk and loads it as the first 16 words of the message
+// schedule
+pub fn load_block<const N: usize>(input: &[u8; N]) -> [u32; 16] {
+    // The message, the '1' bit and the 64-bit length have to fit in one chunk
+    assert!(N * 8 + 1 + 64 <= 512);
+    let (chunks, trailing) = input.as_chunks::<4>();
+    let mut w = [0u32; 16];
+    for (wi, chunk) in w.iter_mut().zip(chunks) {
+        *wi = u32::from_be_bytes(*chunk);
+    }
+    let mut trailing4 = [0u8; 4];
+    trailing4[..trailing.len()].copy_from_slice(trailing);
+    trailing4[trailing.len()] = 0x80;
+    w[chunks.len()] = u32::from_be_bytes(trailing4);
+    w[15] = (N * 8) as u32;
+    w
+}
+
+type Callback<'a> = Box<dyn FnOnce(u32) + 'a>;
+
+/// Batches checksum computations of `N`-byte messages so that they can be hashed in parallel.
+///
+/// The checksum is the leading four bytes of either SHA-256 (as in BIP39 mnemonics) or double
+/// SHA-256 (as in Base58Check) of the message.
+pub struct ParallelChecksum<'a, const N: usize, B: Backend = ShaNi> {
+    double: bool,
+    queue: ArrayVec<([u8; N], Callback<'a>), MAX_PARALLELISM>,
+    backend: std::marker::PhantomData<B>,
+}
+
+impl<'a, const N: usize, B: Backend> ParallelChecksum<'a, N, B> {
+    /// Computes the leading bytes of SHA-256.
+    pub fn single() -> Self {
+        Self {
+            double: false,
+            queue: ArrayVec::new(),
+            backend: std::marker::PhantomData,
+        }
+    }
+
+    /// Computes the leading bytes of SHA-256 applied twice.
+    pub fn double() -> Self {
+        Self {
+            double: true,
+            queue: ArrayVec::new(),
+            backend: std::marker::PhantomData,
+        }
+    }
+
+    fn flush(&mut self) {
+        let inputs: ArrayVec<[u8; N], MAX_PARALLELISM> =
+            self.queue.iter().map(|(string, _)| *string).collect();
+        let checksums = B::checksums(&inputs, self.double);
+        for (output, (_, callback)) in checksums.into_iter().zip(self.queue.drain(..)) {
+            callback(output);
+        }
+    }
+
+    pub fn compute_later(&mut self, string: [u8; N], callback: Callback<'a>) {
+        if self.queue.len() == B::PARALLELISM {
+            self.flush();
+        }
+        self.queue.push((string, callback));
+    }
+
+    pub fn finalize(mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,127 @@
+// Multi-buffer SHA-256 on top of the SHA-NI extension, as introduced in attempt9. Several
+// independent single-block messages are hashed in lockstep so that the latency of
+// `sha256rnds2` is hidden.
+
+use super::{load_block, Backend, MAX_PARALLELISM};
+use core::arch::x86_64::{_mm_sha256msg1_epu32, _mm_sha256msg2_epu32, _mm_sha256rnds2_epu32};
+use crunchy::unroll;
+use std::simd::{simd_swizzle, u32x4};
+
+// Loads a 256-bit hash as the message schedule for the second round of a double SHA-256.
+pub fn adapt_iterated(h: (u32x4, u32x4)) -> [u32x4; 4] {
+    let (h0145, h2367) = h;
+    [
+        simd_swizzle!(h0145, h2367, [3, 2, 7, 6]),
+        simd_swizzle!(h0145, h2367, [1, 0, 5, 4]),
+        u32x4::from_array([0x80000000, 0, 0, 0]),
+        u32x4::from_array([0, 0, 0, 256]),
+    ]
+}
+
+// Hashes N single-chunk messages in parallel. The output is in the ABEF/CDGH layout expected by
+// SHA-NI.
+#[inline(always)]
+#[allow(clippy::needless_range_loop)]
+pub fn core<const N: usize>(leading_w: [[u32x4; 4]; N]) -> [(u32x4, u32x4); N] {
+    // Initialization
+    const H0145: u32x4 = u32x4::from_array([0x9b05688c, 0x510e527f, 0xbb67ae85, 0x6a09e667]);
+    const H2367: u32x4 = u32x4::from_array([0x5be0cd19, 0x1f83d9ab, 0xa54ff53a, 0x3c6ef372]);
+
+    const K: [u32x4; 16] = [
+        u32x4::from_array([0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5]),
+        u32x4::from_array([0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5]),
+        u32x4::from_array([0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3]),
+        u32x4::from_array([0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174]),
+        u32x4::from_array([0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc]),
+        u32x4::from_array([0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da]),
+        u32x4::from_array([0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7]),
+        u32x4::from_array([0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967]),
+        u32x4::from_array([0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13]),
+        u32x4::from_array([0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85]),
+        u32x4::from_array([0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3]),
+        u32x4::from_array([0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070]),
+        u32x4::from_array([0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5]),
+        u32x4::from_array([0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3]),
+        u32x4::from_array([0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208]),
+        u32x4::from_array([0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2]),
+    ];
+
+    // Initialize working variables to current hash value
+    let mut abef = [H0145; N];
+    let mut cdgh = [H2367; N];
+
+    let mut four_rounds = |i, j: usize, wij| {
+        let kwij: u32x4 = K[i] + wij;
+        unsafe {
+            cdgh[j] = _mm_sha256rnds2_epu32(cdgh[j].into(), abef[j].into(), kwij.into()).into();
+            abef[j] = _mm_sha256rnds2_epu32(
+                abef[j].into(),
+                cdgh[j].into(),
+                simd_swizzle!(kwij, [2, 3, 2, 3]).into(),
+            )
+            .into();
+        }
+    };
+
+    let mut w = [[u32x4::splat(0); N]; 16];
+    for i in 0..4 {
+        for j in 0..N {
+            w[i][j] = leading_w[j][i];
+            four_rounds(i, j, w[i][j]);
+        }
+    }
+
+    // Extend the first 16 words into the remaining 48 words w[16..64] of the message schedule array
+    // & Compression function main loop
+    unsafe {
+        unroll! {
+            for i in 4..16 {
+                for j in 0..N {
+                    w[i][j] = _mm_sha256msg2_epu32(
+                        (u32x4::from(_mm_sha256msg1_epu32(w[i - 4][j].into(), w[i - 3][j].into()))
+                            + simd_swizzle!(w[i - 2][j], w[i - 1][j], [1, 2, 3, 4]))
+                        .into(),
+                        w[i - 1][j].into(),
+                    )
+                    .into();
+                    four_rounds(i, j, w[i][j]);
+                }
+            }
+        }
+    }
+
+    // Add the compressed chunk to the current hash value
+    let mut output = [(u32x4::splat(0), u32x4::splat(0)); N];
+    for i in 0..N {
+        output[i] = (H0145 + abef[i], H2367 + cdgh[i]);
+    }
+    output
+}
+
+// Extracts the leading four bytes of the hash as a big-endian number.
+pub fn store_leading_four_bytes(h: (u32x4, u32x4)) -> u32 {
+    h.0[3]
+}
+
+pub struct ShaNi;
+
+const PARALLELISM: usize = 2;
+
+impl Backend for ShaNi {
+    const PARALLELISM: usize = PARALLELISM;
+
+    fn checksums<const N: usize>(inputs: &[[u8; N]], double: bool) -> [u32; MAX_PARALLELISM] {
+        let mut blocks = [[u32x4::splat(0); 4]; PARALLELISM];
+        for (block, input) in blocks.iter_mut().zip(inputs) {
+            let w = load_block(input);
+            *block = std::array::from_fn(|i| u32x4::from_slice(&w[i * 4..i * 4 + 4]));
+        }
+        let mut hashes = core(blocks);
+        if double {
+            hashes = core(hashes.map(adapt_iterated));
+        }
+        let mut checksums = [0; MAX_PARALLELISM];
+        checksums[..PARALLELISM].copy_from_slice(&hashes.map(store_leading_four_bytes));
+        checksums
+    }
+}
@@ -0,0 +1,119 @@
+// Eight messages at a time in the lanes of AVX2 vectors, as in attempt8.
+
+use super::{load_block, Backend, MAX_PARALLELISM};
+use std::simd::u32x8;
+
+// Transposes the message schedules so that each vector holds the same word of all messages
+fn load_blocks<const N: usize>(inputs: &[[u8; N]]) -> [u32x8; 16] {
+    let mut w: [u32x8; 16] = [u32x8::splat(0); 16];
+    for (input_index, input) in inputs.iter().enumerate() {
+        for (wi, word) in w.iter_mut().zip(load_block(input)) {
+            wi[input_index] = word;
+        }
+    }
+    w
+}
+
+fn adapt_iterated(h: [u32x8; 8]) -> [u32x8; 16] {
+    let mut w: [u32x8; 16] = [u32x8::splat(0); 16];
+    w[..8].copy_from_slice(&h);
+    w[8] = u32x8::splat(0x80000000);
+    w[15] = u32x8::splat(256);
+    w
+}
+
+#[inline(always)]
+fn core(leading_w: [u32x8; 16]) -> [u32x8; 8] {
+    // Initialization
+    let mut h0 = u32x8::splat(0x6a09e667);
+    let mut h1 = u32x8::splat(0xbb67ae85);
+    let mut h2 = u32x8::splat(0x3c6ef372);
+    let mut h3 = u32x8::splat(0xa54ff53a);
+    let mut h4 = u32x8::splat(0x510e527f);
+    let mut h5 = u32x8::splat(0x9b05688c);
+    let mut h6 = u32x8::splat(0x1f83d9ab);
+    let mut h7 = u32x8::splat(0x5be0cd19);
+
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    let mut w: [u32x8; 64] = [u32x8::splat(0); 64];
+    w[..16].copy_from_slice(&leading_w);
+
+    // Extend the first 16 words into the remaining 48 words w[16..64] of the message schedule array
+    let rotate_right = |word, count| (word >> count) | (word << (32 - count));
+    for i in 16..64 {
+        let s0 = rotate_right(w[i - 15], 7) ^ rotate_right(w[i - 15], 18) ^ (w[i - 15] >> 3);
+        let s1 = rotate_right(w[i - 2], 17) ^ rotate_right(w[i - 2], 19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16] + s0 + w[i - 7] + s1;
+    }
+
+    // Initialize working variables to current hash value
+    let mut a = h0;
+    let mut b = h1;
+    let mut c = h2;
+    let mut d = h3;
+    let mut e = h4;
+    let mut f = h5;
+    let mut g = h6;
+    let mut h = h7;
+
+    // Compression function main loop
+    let compress = |a, b, c, d: &mut _, e, f, g, h: &mut _, ki, wi| {
+        let s1 = rotate_right(e, 6) ^ rotate_right(e, 11) ^ rotate_right(e, 25);
+        let ch = (e & f) ^ (!e & g);
+        let temp1 = *h + s1 + ch + u32x8::splat(ki) + wi;
+        let s0 = rotate_right(a, 2) ^ rotate_right(a, 13) ^ rotate_right(a, 22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0 + maj;
+        *d += temp1;
+        *h = temp1 + temp2;
+    };
+
+    for i in (0..64).step_by(8) {
+        compress(a, b, c, &mut d, e, f, g, &mut h, K[i], w[i]);
+        compress(h, a, b, &mut c, d, e, f, &mut g, K[i + 1], w[i + 1]);
+        compress(g, h, a, &mut b, c, d, e, &mut f, K[i + 2], w[i + 2]);
+        compress(f, g, h, &mut a, b, c, d, &mut e, K[i + 3], w[i + 3]);
+        compress(e, f, g, &mut h, a, b, c, &mut d, K[i + 4], w[i + 4]);
+        compress(d, e, f, &mut g, h, a, b, &mut c, K[i + 5], w[i + 5]);
+        compress(c, d, e, &mut f, g, h, a, &mut b, K[i + 6], w[i + 6]);
+        compress(b, c, d, &mut e, f, g, h, &mut a, K[i + 7], w[i + 7]);
+    }
+
+    // Add the compressed chunk to the current hash value
+    h0 += a;
+    h1 += b;
+    h2 += c;
+    h3 += d;
+    h4 += e;
+    h5 += f;
+    h6 += g;
+    h7 += h;
+
+    [h0, h1, h2, h3, h4, h5, h6, h7]
+}
+
+pub struct Simd;
+
+impl Backend for Simd {
+    const PARALLELISM: usize = 8;
+
+    fn checksums<const N: usize>(inputs: &[[u8; N]], double: bool) -> [u32; MAX_PARALLELISM] {
+        let mut hashes = core(load_blocks(inputs));
+        if double {
+            hashes = core(adapt_iterated(hashes));
+        }
+        hashes[0].to_array()
+    }
+}
@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+use std::ops::{Add, AddAssign, BitAnd, Mul, MulAssign, Not};
+
+// Just enough bits to fit a 25-byte Base58Check payload. Limbs are stored from least significant
+// to most significant.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct u200(pub u64, pub u64, pub u64, pub u8);
+
+impl u200 {
+    pub const ZERO: u200 = u200(0, 0, 0, 0);
+    pub const MAX: u200 = u200(u64::MAX, u64::MAX, u64::MAX, u8::MAX);
+
+    pub fn to_be_bytes(self) -> [u8; 25] {
+        let mut bytes = [0u8; 25];
+        bytes[0] = self.3;
+        bytes[1..9].copy_from_slice(&self.2.to_be_bytes());
+        bytes[9..17].copy_from_slice(&self.1.to_be_bytes());
+        bytes[17..].copy_from_slice(&self.0.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 25]) -> u200 {
+        u200(
+            u64::from_be_bytes(*bytes[17..].first_chunk::<8>().unwrap()),
+            u64::from_be_bytes(*bytes[9..17].first_chunk::<8>().unwrap()),
+            u64::from_be_bytes(*bytes[1..9].first_chunk::<8>().unwrap()),
+            bytes[0],
+        )
+    }
+
+    pub fn is_zero(self) -> bool {
+        self == u200::ZERO
+    }
+
+    pub fn checked_add(self, other: u200) -> Option<u200> {
+        let (a, carry) = self.0.overflowing_add(other.0);
+        let (b, carry) = self.1.carrying_add(other.1, carry);
+        let (c, carry) = self.2.carrying_add(other.2, carry);
+        let (d, carry) = self.3.carrying_add(other.3, carry);
+        (!carry).then_some(u200(a, b, c, d))
+    }
+
+    pub fn checked_mul(self, other: u64) -> Option<u200> {
+        let (a, carry) = self.0.widening_mul(other);
+        let (b, carry) = self.1.carrying_mul(other, carry);
+        let (c, carry) = self.2.carrying_mul(other, carry);
+        let d = (self.3 as u64).checked_mul(other)?.checked_add(carry)?;
+        Some(u200(a, b, c, d.try_into().ok()?))
+    }
+
+    // Long division by a single limb, from the most significant limb to the least significant one
+    pub fn div_rem(self, divisor: u64) -> (u200, u64) {
+        let mut remainder = 0u128;
+        let mut step = |limb: u64| {
+            let dividend = (remainder << 64) | limb as u128;
+            remainder = dividend % divisor as u128;
+            (dividend / divisor as u128) as u64
+        };
+        let d = step(self.3 as u64) as u8;
+        let c = step(self.2);
+        let b = step(self.1);
+        let a = step(self.0);
+        (u200(a, b, c, d), remainder as u64)
+    }
+}
+
+impl PartialOrd for u200 {
+    fn partial_cmp(&self, other: &u200) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for u200 {
+    fn cmp(&self, other: &u200) -> Ordering {
+        (self.3, self.2, self.1, self.0).cmp(&(other.3, other.2, other.1, other.0))
+    }
+}
+
+impl From<u64> for u200 {
+    fn from(value: u64) -> u200 {
+        u200(value, 0, 0, 0)
+    }
+}
+
+impl Add for u200 {
+    type Output = u200;
+    fn add(self, other: u200) -> u200 {
+        let (a, carry) = self.0.overflowing_add(other.0);
+        let (b, carry) = self.1.carrying_add(other.1, carry);
+        let (c, carry) = self.2.carrying_add(other.2, carry);
+        let (d, _) = self.3.carrying_add(other.3, carry);
+        u200(a, b, c, d)
+    }
+}
+
+impl AddAssign for u200 {
+    fn add_assign(&mut self, other: u200) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<u64> for u200 {
+    type Output = u200;
+    fn mul(self, other: u64) -> u200 {
+        let (a, carry) = self.0.widening_mul(other);
+        let (b, carry) = self.1.carrying_mul(other, carry);
+        let (c, carry) = self.2.carrying_mul(other, carry);
+        let (d, _) = self.3.carrying_mul(other as u8, carry as u8);
+        u200(a, b, c, d)
+    }
+}
+
+impl MulAssign<u64> for u200 {
+    fn mul_assign(&mut self, other: u64) {
+        *self = *self * other;
+    }
+}
+
+impl BitAnd for u200 {
+    type Output = u200;
+    fn bitand(self, other: u200) -> u200 {
+        u200(
+            self.0 & other.0,
+            self.1 & other.1,
+            self.2 & other.2,
+            self.3 & other.3,
+        )
+    }
+}
+
+impl Not for u200 {
+    type Output = u200;
+    fn not(self) -> u200 {
+        u200(!self.0, !self.1, !self.2, !self.3)
+    }
+}
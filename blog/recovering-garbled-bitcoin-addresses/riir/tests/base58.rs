@@ -0,0 +1,78 @@
+use base58::{FromBase58, ToBase58};
+use riir::base58::{decode, encode, DecodeError, MAX_BYTES};
+
+// A tiny xorshift generator, so that the inputs are reproducible
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        let length = (self.next() % (MAX_BYTES as u64 + 1)) as usize;
+        // Leading zeros are the interesting part, so generate them often
+        let leading_zeros = (self.next() % 4) as usize;
+        (0..length)
+            .map(|i| {
+                if i < leading_zeros {
+                    0
+                } else {
+                    self.next() as u8
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn encode_matches_base58_crate() {
+    let mut random = Random(0x2545f4914f6cdd1d);
+    for _ in 0..10000 {
+        let bytes = random.bytes();
+        assert_eq!(encode(&bytes), bytes.to_base58(), "{bytes:?}");
+    }
+}
+
+#[test]
+fn decode_matches_base58_crate() {
+    let mut random = Random(0x9e3779b97f4a7c15);
+    for _ in 0..10000 {
+        let string = random.bytes().to_base58();
+        assert_eq!(
+            decode(&string).unwrap(),
+            string.from_base58().unwrap(),
+            "{string}"
+        );
+    }
+}
+
+#[test]
+fn leading_ones() {
+    assert_eq!(decode("").unwrap(), b"");
+    assert_eq!(decode("111").unwrap(), [0, 0, 0]);
+    assert_eq!(decode("11z").unwrap(), [0, 0, 57]);
+    assert_eq!(encode(&[0, 0, 57]), "11z");
+    assert_eq!(
+        decode("1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX71").unwrap(),
+        "1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX71".from_base58().unwrap()
+    );
+}
+
+#[test]
+fn errors() {
+    assert_eq!(
+        decode("1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX7l"),
+        Err(DecodeError::InvalidCharacter {
+            index: 33,
+            byte: b'l'
+        })
+    );
+    // 26 bytes
+    assert_eq!(decode(&[1; 26].to_base58()), Err(DecodeError::TooLong));
+    assert_eq!(decode(&"1".repeat(26)), Err(DecodeError::TooLong));
+    assert_eq!(decode(&"z".repeat(40)), Err(DecodeError::TooLong));
+}
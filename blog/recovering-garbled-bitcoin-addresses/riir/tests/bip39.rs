@@ -0,0 +1,151 @@
+use riir::bip39::{candidates_for_word, pack_entropy, recover, Error, WORDLIST_LEN};
+use sha2::{Digest, Sha256};
+
+// Entropy and word indices of mnemonics from the BIP39 reference vectors, see
+// https://github.com/trezor/python-mnemonic/blob/master/vectors.json
+const VECTORS: [(&str, &[u16]); 7] = [
+    (
+        "00000000000000000000000000000000",
+        &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3],
+    ),
+    (
+        "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+        &[
+            1019, 2015, 1790, 2039, 1983, 1533, 2031, 1919, 1019, 2015, 1790, 2040,
+        ],
+    ),
+    (
+        "80808080808080808080808080808080",
+        &[1028, 32, 257, 8, 64, 514, 16, 128, 1028, 32, 257, 4],
+    ),
+    (
+        "ffffffffffffffffffffffffffffffff",
+        &[
+            2047, 2047, 2047, 2047, 2047, 2047, 2047, 2047, 2047, 2047, 2047, 2037,
+        ],
+    ),
+    (
+        "9e885d952ad362caeb4efe34a8e91bd2",
+        &[
+            1268, 535, 810, 685, 433, 811, 1385, 1790, 421, 570, 567, 1313,
+        ],
+    ),
+    (
+        "6610b25967cdcca9d59875f5cb50b0ea75433311869e930b",
+        &[
+            816, 1068, 1202, 1660, 1766, 679, 691, 117, 1966, 724, 353, 1703, 673, 1228, 560, 1694,
+            1176, 722,
+        ],
+    ),
+    (
+        "68a79eaca2324873eacc50cb9c6eca8cc68ea5d936f98787c60c7ebc74e6ce7c",
+        &[
+            837, 487, 1369, 547, 292, 463, 1369, 1104, 1628, 1819, 1429, 204, 839, 663, 806, 1785,
+            1084, 497, 1048, 2027, 1594, 923, 463, 1024,
+        ],
+    ),
+];
+
+fn hex(string: &str) -> Vec<u8> {
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn pack(words: &[u16]) -> Vec<u8> {
+    let (last_word, leading_words) = words.split_last().unwrap();
+    let checksum_bits = words.len() / 3;
+    let last_word_entropy = last_word >> checksum_bits;
+    match words.len() {
+        12 => pack_entropy::<16>(leading_words, last_word_entropy).to_vec(),
+        18 => pack_entropy::<24>(leading_words, last_word_entropy).to_vec(),
+        24 => pack_entropy::<32>(leading_words, last_word_entropy).to_vec(),
+        _ => unreachable!(),
+    }
+}
+
+// The real words don't matter, only their indices
+fn wordlist() -> Vec<String> {
+    (0..WORDLIST_LEN).map(|i| format!("w{i:04}")).collect()
+}
+
+fn mnemonic(words: &[u16]) -> String {
+    words
+        .iter()
+        .map(|i| format!("w{i:04}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn packs_reference_vectors() {
+    for (entropy, words) in VECTORS {
+        let entropy = hex(entropy);
+        assert_eq!(pack(words), entropy);
+        // The bits of the last word that aren't entropy are the leading bits of the hash
+        let checksum_bits = words.len() / 3;
+        let checksum = Sha256::digest(&entropy)[0] >> (8 - checksum_bits);
+        assert_eq!(
+            words.last().unwrap() & ((1 << checksum_bits) - 1),
+            checksum as u16
+        );
+    }
+}
+
+#[test]
+fn recovers_reference_vectors() {
+    let wordlist = wordlist();
+    for (_, words) in VECTORS {
+        let expected = mnemonic(words);
+        // Unknown words in the middle and at the end
+        let mut garbled: Vec<String> = expected.split(' ').map(String::from).collect();
+        garbled[5] = "?".to_string();
+        *garbled.last_mut().unwrap() = "?".to_string();
+        let found = recover(&garbled, &wordlist).unwrap();
+        assert!(found.contains(&expected), "{expected}");
+        // Only the checksum rules out the other words, so about 1 in 2^checksum_bits passes
+        let checksum_bits = words.len() / 3;
+        assert!(found.len() < 2 * ((WORDLIST_LEN * WORDLIST_LEN) >> checksum_bits));
+    }
+}
+
+#[test]
+fn word_candidates() {
+    let wordlist: Vec<String> = ["abandon", "ability", "able", "about", "zoo"]
+        .map(String::from)
+        .to_vec();
+    assert_eq!(candidates_for_word("about", &wordlist), Ok(vec![3]));
+    assert_eq!(candidates_for_word("?", &wordlist), Ok(vec![0, 1, 2, 3, 4]));
+    // Two edits away
+    assert_eq!(candidates_for_word("abuot", &wordlist), Ok(vec![3]));
+    assert_eq!(candidates_for_word("abot", &wordlist), Ok(vec![2, 3]));
+    assert_eq!(
+        candidates_for_word("xylophone", &wordlist),
+        Err(Error::NoSimilarWords {
+            word: "xylophone".to_string()
+        })
+    );
+}
+
+#[test]
+fn malformed_mnemonics() {
+    let wordlist = wordlist();
+    let words: Vec<String> = vec!["w0000".to_string(); 13];
+    assert_eq!(
+        recover(&words, &wordlist),
+        Err(Error::InvalidWordCount { count: 13 })
+    );
+    assert_eq!(
+        recover(&words, &wordlist[..100]),
+        Err(Error::InvalidWordlist { length: 100 })
+    );
+    let mut words = vec!["w0000".to_string(); 12];
+    words[3] = "nonsense".to_string();
+    assert_eq!(
+        recover(&words, &wordlist),
+        Err(Error::NoSimilarWords {
+            word: "nonsense".to_string()
+        })
+    );
+}
@@ -0,0 +1,112 @@
+use riir::recover::{narrow, recover, recover_with, Error, Pins};
+use riir::sha256::{Scalar, ShaNi, Simd};
+
+#[test]
+fn recovers_address() {
+    assert_eq!(
+        recover("1lbcfr7sahtd9cgdqo3htmtkv8lk4znx71", &Pins::new()).unwrap(),
+        ["1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX71"]
+    );
+}
+
+#[test]
+fn pins() {
+    let mut pins = Pins::new();
+    pins.insert(1, b'L');
+    assert_eq!(
+        recover("1lbcfr7sahtd9cgdqo3htmtkv8lk4znx71", &pins).unwrap(),
+        ["1Lbcfr7sAHTD9CgdQo3HTMTkV8LK4ZnX71"]
+    );
+    pins.insert(8, b'a');
+    assert!(recover("1lbcfr7sahtd9cgdqo3htmtkv8lk4znx71", &pins)
+        .unwrap()
+        .is_empty());
+    pins.insert(2, b'c');
+    assert_eq!(
+        recover("1lbcfr7sahtd9cgdqo3htmtkv8lk4znx71", &pins),
+        Err(Error::InvalidPin {
+            index: 2,
+            byte: b'c'
+        })
+    );
+    pins.clear();
+    pins.insert(34, b'1');
+    assert_eq!(
+        recover("1lbcfr7sahtd9cgdqo3htmtkv8lk4znx71", &pins),
+        Err(Error::PinOutOfRange {
+            index: 34,
+            length: 34
+        })
+    );
+}
+
+#[test]
+fn narrowing_agrees_with_searching() {
+    let garbled_address = "1Lbcfr7sAHTD9CgdQo3htmtkv8lk4znx71";
+    let candidates = recover(garbled_address, &Pins::new()).unwrap();
+    assert_eq!(candidates.len(), 1);
+    for byte in [b'T', b't'] {
+        let mut pins = Pins::new();
+        pins.insert(20, byte);
+        assert_eq!(
+            narrow(garbled_address, &candidates, &pins).unwrap(),
+            recover(garbled_address, &pins).unwrap()
+        );
+    }
+    let mut pins = Pins::new();
+    pins.insert(40, b'x');
+    assert_eq!(
+        narrow(garbled_address, &candidates, &pins),
+        Err(Error::PinOutOfRange {
+            index: 40,
+            length: 34
+        })
+    );
+}
+
+#[test]
+fn backends_agree() {
+    let garbled_address = "1Lbcfr7sAHTD9CgdQo3htmtkv8lk4znx71";
+    let found = recover_with::<ShaNi>(garbled_address, &Pins::new()).unwrap();
+    assert!(!found.is_empty());
+    assert_eq!(
+        recover_with::<Simd>(garbled_address, &Pins::new()).unwrap(),
+        found
+    );
+    assert_eq!(
+        recover_with::<Scalar>(garbled_address, &Pins::new()).unwrap(),
+        found
+    );
+}
+
+#[test]
+fn leading_ones() {
+    // The RIPEMD-160 hash is all zeros, so the payload has 21 leading zero bytes
+    assert_eq!(
+        recover("1111111111111111111114olvt2", &Pins::new()).unwrap(),
+        ["1111111111111111111114oLvT2"]
+    );
+    // An extra leading '1' adds a zero byte instead of being ignored
+    assert_eq!(
+        recover("11lbcfr7sahtd9cgdqo3htmtkv8lk4znx71", &Pins::new()),
+        Err(Error::InvalidLength {
+            length: 35,
+            leading_ones: 2,
+            min_length: 33,
+            max_length: 34,
+        })
+    );
+    assert_eq!(
+        recover("1lbcfr7sahtd9cgdqo3htmtkv8lk4zn", &Pins::new()),
+        Err(Error::InvalidLength {
+            length: 31,
+            leading_ones: 1,
+            min_length: 33,
+            max_length: 34,
+        })
+    );
+    assert_eq!(
+        recover(&"1".repeat(26), &Pins::new()),
+        Err(Error::TooManyLeadingOnes { count: 26 })
+    );
+}
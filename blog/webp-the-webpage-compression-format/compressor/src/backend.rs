@@ -0,0 +1,153 @@
+// Image formats the packed pixels can be stored in. WebP is what the page uses; the other formats
+// exist to show, file by file, how much of the gain comes from VP8L itself rather than from packing
+// text into pixels. Every backend encodes the pixels of a tile and decodes RGBA, and decoding finds
+// the format by its signature, so `decompress` takes any of them.
+
+mod libwebp;
+mod png;
+mod qoi;
+
+use crate::config::{Encoder, EncoderConfig};
+use crate::pixels::Pixels;
+use crate::Error;
+
+pub trait Backend: Sync {
+    // The MIME type to use in data URLs, or None if browsers can't decode the format
+    fn mime_type(&self) -> Option<&'static str>;
+
+    // Whether the data starts with an image in this format
+    fn detect(&self, data: &[u8]) -> bool;
+
+    fn encode(
+        &self,
+        pixels: &Pixels,
+        width: u32,
+        height: u32,
+        config: &EncoderConfig,
+    ) -> Result<Vec<u8>, Error>;
+
+    // The width and height of the image at the start of the data
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)>;
+
+    // Decodes the image at the start of the data into RGBA. Returns the pixels and the length of
+    // the image in bytes, as images are stored back to back.
+    fn decode(&self, data: &[u8]) -> Result<(Vec<u8>, usize), Error>;
+}
+
+struct Libwebp;
+struct Native;
+
+const RIFF_HEADER_LEN: usize = 8;
+
+// Each RIFF container starts with its own length
+fn riff_len(data: &[u8]) -> Option<usize> {
+    let size = u32::from_le_bytes(data.get(4..8)?.try_into().unwrap()) as usize;
+    // Chunks are padded to an even size
+    Some(RIFF_HEADER_LEN + size + size % 2)
+}
+
+fn webp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let features = webp::BitstreamFeatures::new(data.get(..riff_len(data)?)?)?;
+    Some((features.width(), features.height()))
+}
+
+fn decode_webp(data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+    let len = riff_len(data).ok_or(Error::Decode)?;
+    let image = webp::Decoder::new(data.get(..len).ok_or(Error::Decode)?)
+        .decode()
+        .ok_or(Error::Decode)?;
+    let rgba = if image.is_alpha() {
+        image.to_vec()
+    } else {
+        image
+            .chunks(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect()
+    };
+    Ok((rgba, len))
+}
+
+impl Backend for Libwebp {
+    fn mime_type(&self) -> Option<&'static str> {
+        Some("image/webp")
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP")
+    }
+
+    fn encode(
+        &self,
+        pixels: &Pixels,
+        width: u32,
+        height: u32,
+        config: &EncoderConfig,
+    ) -> Result<Vec<u8>, Error> {
+        libwebp::encode(pixels, width, height, config)
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        webp_dimensions(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+        decode_webp(data)
+    }
+}
+
+impl Backend for Native {
+    fn mime_type(&self) -> Option<&'static str> {
+        Some("image/webp")
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        Libwebp.detect(data)
+    }
+
+    fn encode(
+        &self,
+        pixels: &Pixels,
+        width: u32,
+        height: u32,
+        config: &EncoderConfig,
+    ) -> Result<Vec<u8>, Error> {
+        let argb = (0..(width * height) as usize)
+            .map(|i| {
+                let [r, g, b, a] = pixels.get(i);
+                u32::from_be_bytes([a, r, g, b])
+            })
+            .collect();
+        Ok(crate::vp8l::encode(argb, width, height, config.method))
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        webp_dimensions(data)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+        decode_webp(data)
+    }
+}
+
+impl Encoder {
+    pub fn backend(self) -> &'static dyn Backend {
+        match self {
+            Self::Libwebp => &Libwebp,
+            Self::Native => &Native,
+            Self::Png => &png::Png,
+            Self::Qoi => &qoi::Qoi,
+        }
+    }
+}
+
+// The encoder of the image at the start of the data, or libwebp for any WebP image
+pub fn detect_encoder(data: &[u8]) -> Option<Encoder> {
+    [Encoder::Libwebp, Encoder::Png, Encoder::Qoi]
+        .into_iter()
+        .find(|encoder| encoder.backend().detect(data))
+}
+
+// The backend that can decode the image at the start of the data
+pub fn detect(data: &[u8]) -> Option<&'static dyn Backend> {
+    detect_encoder(data).map(Encoder::backend)
+}
@@ -0,0 +1,71 @@
+// Encoding with libwebp without an RGBA copy of the image. The webp crate only takes a finished
+// RGBA buffer, which WebPPictureImportRGBA then copies into the picture, so a tile would be held as
+// pixels twice before the encoder even starts. Instead, the picture is allocated directly and its
+// ARGB rows are filled from the pixels.
+
+use crate::config::EncoderConfig;
+use crate::pixels::Pixels;
+use crate::Error;
+use libwebp_sys::{
+    WebPEncode, WebPEncodingError, WebPMemoryWrite, WebPMemoryWriter, WebPMemoryWriterClear,
+    WebPMemoryWriterInit, WebPPicture, WebPPictureAlloc, WebPPictureFree, WebPValidateConfig,
+};
+
+// Frees the picture buffers on every path
+struct Picture(WebPPicture);
+
+impl Drop for Picture {
+    fn drop(&mut self) {
+        unsafe { WebPPictureFree(&mut self.0) };
+    }
+}
+
+pub fn encode(
+    pixels: &Pixels,
+    width: u32,
+    height: u32,
+    config: &EncoderConfig,
+) -> Result<Vec<u8>, Error> {
+    let config = config.to_webp()?;
+    if unsafe { WebPValidateConfig(&config) } == 0 {
+        return Err(Error::Encode(
+            WebPEncodingError::VP8_ENC_ERROR_INVALID_CONFIGURATION,
+        ));
+    }
+
+    let mut picture = WebPPicture::new()
+        .map_err(|()| Error::Encode(WebPEncodingError::VP8_ENC_ERROR_INVALID_CONFIGURATION))?;
+    picture.use_argb = 1;
+    picture.width = width as i32;
+    picture.height = height as i32;
+    let mut picture = Picture(picture);
+    if unsafe { WebPPictureAlloc(&mut picture.0) } == 0 {
+        return Err(Error::Encode(picture.0.error_code));
+    }
+
+    let stride = picture.0.argb_stride as usize;
+    for y in 0..height as usize {
+        // Rows may be padded, so each one is borrowed on its own
+        let row = unsafe {
+            std::slice::from_raw_parts_mut(picture.0.argb.add(y * stride), width as usize)
+        };
+        for (x, argb) in row.iter_mut().enumerate() {
+            let [r, g, b, a] = pixels.get(y * width as usize + x);
+            *argb = u32::from_be_bytes([a, r, g, b]);
+        }
+    }
+
+    let mut writer = std::mem::MaybeUninit::<WebPMemoryWriter>::uninit();
+    unsafe { WebPMemoryWriterInit(writer.as_mut_ptr()) };
+    let mut writer = unsafe { writer.assume_init() };
+    picture.0.writer = Some(WebPMemoryWrite);
+    picture.0.custom_ptr = &mut writer as *mut WebPMemoryWriter as *mut std::ffi::c_void;
+    let ok = unsafe { WebPEncode(&config, &mut picture.0) } != 0;
+    let webp = if ok {
+        Ok(unsafe { std::slice::from_raw_parts(writer.mem, writer.size) }.to_vec())
+    } else {
+        Err(Error::Encode(picture.0.error_code))
+    };
+    unsafe { WebPMemoryWriterClear(&mut writer) };
+    webp
+}
@@ -0,0 +1,271 @@
+// PNG: each row is filtered by predicting bytes from their neighbours, then the whole image is
+// compressed with DEFLATE. Unlike VP8L, DEFLATE has one Huffman code per block, 32 KiB of history
+// and 1D distances only, which is the difference the post is about.
+//
+// Encoders pick filters by heuristics, which are notoriously unreliable, so every strategy is
+// tried and the smallest output wins: each of the five filters for all rows, and the usual
+// adaptive choice of the filter with the smallest sum of absolute values per row. The image is
+// stored as grayscale or RGB when the pixels allow it, as a good PNG encoder would.
+
+use super::Backend;
+use crate::config::EncoderConfig;
+use crate::pixels::Pixels;
+use crate::Error;
+use std::io::{Read, Write};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const COLOR_GRAY: u8 = 0;
+const COLOR_RGB: u8 = 2;
+const COLOR_RGBA: u8 = 6;
+// From method 6 on, the winning strategy is recompressed with Zopfli
+const ZOPFLI_METHOD: i32 = 6;
+
+pub struct Png;
+
+#[derive(Clone, Copy)]
+enum Strategy {
+    Fixed(u8),
+    Adaptive,
+}
+
+const STRATEGIES: [Strategy; 6] = [
+    Strategy::Fixed(0),
+    Strategy::Fixed(1),
+    Strategy::Fixed(2),
+    Strategy::Fixed(3),
+    Strategy::Fixed(4),
+    Strategy::Adaptive,
+];
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// The predictor of the filter for byte i, given the left, up and up-left bytes
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> u8 {
+    match filter {
+        0 => 0,
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        4 => paeth(left, up, up_left),
+        _ => unreachable!(),
+    }
+}
+
+fn filter_row(filter: u8, row: &[u8], previous: &[u8], bpp: usize, output: &mut Vec<u8>) {
+    output.push(filter);
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        output.push(row[i].wrapping_sub(predict(filter, left, previous[i], up_left)));
+    }
+}
+
+fn filter_image(rows: &[&[u8]], bpp: usize, strategy: Strategy) -> Vec<u8> {
+    let zero_row = vec![0; rows[0].len()];
+    let mut output = Vec::with_capacity(rows.len() * (rows[0].len() + 1));
+    let mut candidate = Vec::new();
+    for (y, row) in rows.iter().enumerate() {
+        let previous = if y == 0 { &zero_row[..] } else { rows[y - 1] };
+        match strategy {
+            Strategy::Fixed(filter) => filter_row(filter, row, previous, bpp, &mut output),
+            Strategy::Adaptive => {
+                // Bytes are treated as signed, so that small negative residuals count as small
+                let best = (0..5)
+                    .min_by_key(|&filter| {
+                        candidate.clear();
+                        filter_row(filter, row, previous, bpp, &mut candidate);
+                        candidate[1..]
+                            .iter()
+                            .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                            .sum::<u64>()
+                    })
+                    .unwrap();
+                filter_row(best, row, previous, bpp, &mut output);
+            }
+        }
+    }
+    output
+}
+
+fn zlib(data: &[u8], zopfli: bool) -> Vec<u8> {
+    if zopfli {
+        let mut output = Vec::new();
+        zopfli::compress(
+            zopfli::Options::default(),
+            zopfli::Format::Zlib,
+            data,
+            &mut output,
+        )
+        .unwrap();
+        return output;
+    }
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend((data.len() as u32).to_be_bytes());
+    output.extend(kind);
+    output.extend(data);
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(kind);
+    hasher.update(data);
+    output.extend(hasher.finalize().to_be_bytes());
+}
+
+impl Backend for Png {
+    fn mime_type(&self) -> Option<&'static str> {
+        Some("image/png")
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(&SIGNATURE)
+    }
+
+    fn encode(
+        &self,
+        pixels: &Pixels,
+        width: u32,
+        height: u32,
+        config: &EncoderConfig,
+    ) -> Result<Vec<u8>, Error> {
+        let pixels = (0..(width * height) as usize).map(|i| pixels.get(i));
+        let (color_type, bpp) = if pixels
+            .clone()
+            .all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255)
+        {
+            (COLOR_GRAY, 1)
+        } else if pixels.clone().all(|p| p[3] == 255) {
+            (COLOR_RGB, 3)
+        } else {
+            (COLOR_RGBA, 4)
+        };
+        let samples: Vec<u8> = pixels.flat_map(|p| p.into_iter().take(bpp)).collect();
+        let rows: Vec<&[u8]> = samples.chunks(width as usize * bpp).collect();
+
+        let filtered = STRATEGIES.map(|strategy| filter_image(&rows, bpp, strategy));
+        let compressed = filtered.iter().map(|data| zlib(data, false));
+        let (best, mut idat) = compressed
+            .enumerate()
+            .min_by_key(|(_, data)| data.len())
+            .unwrap();
+        if config.method >= ZOPFLI_METHOD {
+            let zopfli = zlib(&filtered[best], true);
+            if zopfli.len() < idat.len() {
+                idat = zopfli;
+            }
+        }
+
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        // 8 bits per sample, deflate, adaptive filtering, no interlacing
+        header.extend([8, color_type, 0, 0, 0]);
+        let mut output = SIGNATURE.to_vec();
+        write_chunk(&mut output, b"IHDR", &header);
+        write_chunk(&mut output, b"IDAT", &idat);
+        write_chunk(&mut output, b"IEND", &[]);
+        Ok(output)
+    }
+
+    // IHDR is always the first chunk
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        let header = data.get(SIGNATURE.len() + 8..SIGNATURE.len() + 16)?;
+        Some((
+            u32::from_be_bytes(header[..4].try_into().unwrap()),
+            u32::from_be_bytes(header[4..].try_into().unwrap()),
+        ))
+    }
+
+    // Only reads what `encode` writes
+    fn decode(&self, data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+        let mut position = SIGNATURE.len();
+        let mut header = None;
+        let mut idat = Vec::new();
+        loop {
+            let chunk_len = data
+                .get(position..position + 4)
+                .ok_or(Error::Decode)?
+                .try_into()
+                .unwrap();
+            let chunk_len = u32::from_be_bytes(chunk_len) as usize;
+            let kind = data.get(position + 4..position + 8).ok_or(Error::Decode)?;
+            let body = data
+                .get(position + 8..position + 8 + chunk_len)
+                .ok_or(Error::Decode)?;
+            position += 12 + chunk_len;
+            match kind {
+                b"IHDR" => header = Some(body),
+                b"IDAT" => idat.extend(body),
+                b"IEND" => break,
+                _ => {}
+            }
+        }
+        if position > data.len() {
+            return Err(Error::Decode);
+        }
+        let header = header
+            .filter(|header| header.len() == 13)
+            .ok_or(Error::Decode)?;
+        let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        let bpp = match header[8..] {
+            [8, COLOR_GRAY, 0, 0, 0] => 1,
+            [8, COLOR_RGB, 0, 0, 0] => 3,
+            [8, COLOR_RGBA, 0, 0, 0] => 4,
+            _ => return Err(Error::Decode),
+        };
+
+        let mut filtered = Vec::new();
+        flate2::read::ZlibDecoder::new(&idat[..])
+            .read_to_end(&mut filtered)
+            .map_err(|_| Error::Decode)?;
+        let stride = width * bpp;
+        if filtered.len() != height * (stride + 1) {
+            return Err(Error::Decode);
+        }
+        let mut samples = vec![0; height * stride];
+        for (y, line) in filtered.chunks(stride + 1).enumerate() {
+            let filter = line[0];
+            if filter > 4 {
+                return Err(Error::Decode);
+            }
+            for i in 0..stride {
+                let at = y * stride + i;
+                let left = if i >= bpp { samples[at - bpp] } else { 0 };
+                let up = if y > 0 { samples[at - stride] } else { 0 };
+                let up_left = if y > 0 && i >= bpp {
+                    samples[at - stride - bpp]
+                } else {
+                    0
+                };
+                samples[at] = line[i + 1].wrapping_add(predict(filter, left, up, up_left));
+            }
+        }
+        let rgba = samples
+            .chunks(bpp)
+            .flat_map(|sample| match *sample {
+                [gray] => [gray, gray, gray, 255],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            })
+            .collect();
+        Ok((rgba, position))
+    }
+}
@@ -0,0 +1,173 @@
+// QOI, the "Quite OK Image" format: a byte-oriented encoding of each pixel as a run, a reference to
+// one of 64 recently seen colors, a small difference from the previous pixel, or the pixel itself.
+// There is no entropy coding and no backward references beyond the color table, so it shows what's
+// left of the gain without any.
+//
+// See https://qoiformat.org/qoi-specification.pdf.
+
+use super::Backend;
+use crate::config::EncoderConfig;
+use crate::pixels::Pixels;
+use crate::width::MAX_DIMENSION;
+use crate::Error;
+
+const MAGIC: &[u8; 4] = b"qoif";
+const HEADER_LEN: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const MAX_RUN: usize = 62;
+
+pub struct Qoi;
+
+fn hash([r, g, b, a]: [u8; 4]) -> usize {
+    (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64
+}
+
+impl Backend for Qoi {
+    fn mime_type(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    fn encode(
+        &self,
+        pixels: &Pixels,
+        width: u32,
+        height: u32,
+        _config: &EncoderConfig,
+    ) -> Result<Vec<u8>, Error> {
+        let mut output = MAGIC.to_vec();
+        output.extend(width.to_be_bytes());
+        output.extend(height.to_be_bytes());
+        // RGBA, sRGB with linear alpha
+        output.extend([4, 0]);
+
+        let mut index = [[0u8; 4]; 64];
+        let mut previous = [0, 0, 0, 255];
+        let mut run = 0;
+        for pixel in (0..(width * height) as usize).map(|i| pixels.get(i)) {
+            if pixel == previous {
+                run += 1;
+                if run == MAX_RUN {
+                    output.push(OP_RUN | (run - 1) as u8);
+                    run = 0;
+                }
+                continue;
+            }
+            if run > 0 {
+                output.push(OP_RUN | (run - 1) as u8);
+                run = 0;
+            }
+
+            let slot = hash(pixel);
+            if index[slot] == pixel {
+                output.push(OP_INDEX | slot as u8);
+            } else if pixel[3] == previous[3] {
+                let [dr, dg, db] =
+                    [0, 1, 2].map(|channel| pixel[channel].wrapping_sub(previous[channel]) as i8);
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+                if [dr, dg, db].iter().all(|d| (-2..=1).contains(d)) {
+                    let [dr, dg, db] = [dr, dg, db].map(|d| (d + 2) as u8);
+                    output.push(OP_DIFF | dr << 4 | dg << 2 | db);
+                } else if (-32..=31).contains(&dg)
+                    && (-8..=7).contains(&dr_dg)
+                    && (-8..=7).contains(&db_dg)
+                {
+                    output.push(OP_LUMA | (dg + 32) as u8);
+                    output.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    output.extend([OP_RGB, pixel[0], pixel[1], pixel[2]]);
+                }
+            } else {
+                output.push(OP_RGBA);
+                output.extend(pixel);
+            }
+            index[slot] = pixel;
+            previous = pixel;
+        }
+        if run > 0 {
+            output.push(OP_RUN | (run - 1) as u8);
+        }
+        output.extend(END_MARKER);
+        Ok(output)
+    }
+
+    fn dimensions(&self, data: &[u8]) -> Option<(u32, u32)> {
+        let header = data.get(4..12)?;
+        Some((
+            u32::from_be_bytes(header[..4].try_into().unwrap()),
+            u32::from_be_bytes(header[4..].try_into().unwrap()),
+        ))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+        let header = data.get(..HEADER_LEN).ok_or(Error::Decode)?;
+        let width = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        let height = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        // The header of a corrupted image can claim anything. Each byte decodes to a run of at most
+        // MAX_RUN pixels, so a larger image can't fit in the data.
+        if width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(Error::Decode);
+        }
+        let pixels = (width as usize)
+            .checked_mul(height as usize)
+            .filter(|&pixels| pixels <= data.len().saturating_mul(MAX_RUN))
+            .ok_or(Error::Decode)?;
+
+        let mut rgba = Vec::new();
+        let mut index = [[0u8; 4]; 64];
+        let mut pixel = [0, 0, 0, 255];
+        let mut position = HEADER_LEN;
+        let mut next = || -> Result<u8, Error> {
+            let byte = *data.get(position).ok_or(Error::Decode)?;
+            position += 1;
+            Ok(byte)
+        };
+        while rgba.len() < pixels * 4 {
+            let op = next()?;
+            match op {
+                OP_RGB => pixel = [next()?, next()?, next()?, pixel[3]],
+                OP_RGBA => pixel = [next()?, next()?, next()?, next()?],
+                _ => match op & 0xc0 {
+                    OP_INDEX => pixel = index[op as usize],
+                    OP_DIFF => {
+                        for (channel, shift) in [4, 2, 0].into_iter().enumerate() {
+                            let delta = (op >> shift & 3).wrapping_sub(2);
+                            pixel[channel] = pixel[channel].wrapping_add(delta);
+                        }
+                    }
+                    OP_LUMA => {
+                        let dg = (op & 0x3f).wrapping_sub(32);
+                        let byte = next()?;
+                        pixel[0] =
+                            pixel[0].wrapping_add(dg.wrapping_add((byte >> 4).wrapping_sub(8)));
+                        pixel[1] = pixel[1].wrapping_add(dg);
+                        pixel[2] =
+                            pixel[2].wrapping_add(dg.wrapping_add((byte & 15).wrapping_sub(8)));
+                    }
+                    _ => {
+                        // The run includes this pixel
+                        for _ in 0..op & 0x3f {
+                            rgba.extend(pixel);
+                        }
+                    }
+                },
+            }
+            index[hash(pixel)] = pixel;
+            rgba.extend(pixel);
+        }
+        if rgba.len() != pixels * 4 || data.get(position..position + 8) != Some(&END_MARKER) {
+            return Err(Error::Decode);
+        }
+        Ok((rgba, position + 8))
+    }
+}
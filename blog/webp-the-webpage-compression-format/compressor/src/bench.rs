@@ -0,0 +1,277 @@
+// Compares the compressor to general-purpose compression formats on a corpus, all in-process, with
+// the strongest settings each format offers. The compressor runs once per image backend, so that
+// WebP can be compared to PNG and QOI holding exactly the same pixels.
+
+use crate::config::{Encoder, EncoderConfig};
+use crate::error::{read, read_dir};
+use crate::{Error, Options};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Aligned columns with sizes, ratios and timings, one row per file and codec
+    Table,
+    /// One row per file and codec, with sizes, ratios and timings
+    Csv,
+    /// The same as CSV, as an array of objects
+    Json,
+}
+
+// Anything a codec can fail with, e.g. the compressor rejecting an empty file, only fails that file
+type CodecError = Box<dyn std::error::Error>;
+
+type Transform<'a> = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, CodecError> + 'a>;
+
+struct Codec<'a> {
+    name: &'static str,
+    compress: Transform<'a>,
+    decompress: Transform<'a>,
+}
+
+fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, CodecError> {
+    let mut output = Vec::new();
+    flate2::read::GzDecoder::new(data).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+fn codecs(args: &Options) -> Vec<Codec<'_>> {
+    let mut codecs = vec![
+        Codec {
+            name: "gzip",
+            compress: Box::new(|data| {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }),
+            decompress: Box::new(gzip_decompress),
+        },
+        Codec {
+            name: "zopfli",
+            compress: Box::new(|data| {
+                let mut output = Vec::new();
+                zopfli::compress(
+                    zopfli::Options::default(),
+                    zopfli::Format::Gzip,
+                    data,
+                    &mut output,
+                )?;
+                Ok(output)
+            }),
+            decompress: Box::new(gzip_decompress),
+        },
+        Codec {
+            name: "brotli",
+            compress: Box::new(|mut data| {
+                // The same as `brotli --best`
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 11,
+                    lgwin: 24,
+                    ..Default::default()
+                };
+                let mut output = Vec::new();
+                brotli::BrotliCompress(&mut data, &mut output, &params)?;
+                Ok(output)
+            }),
+            decompress: Box::new(|mut data| {
+                let mut output = Vec::new();
+                brotli::BrotliDecompress(&mut data, &mut output)?;
+                Ok(output)
+            }),
+        },
+        Codec {
+            name: "bzip2",
+            compress: Box::new(|data| {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }),
+            decompress: Box::new(|data| {
+                let mut output = Vec::new();
+                bzip2::read::BzDecoder::new(data).read_to_end(&mut output)?;
+                Ok(output)
+            }),
+        },
+    ];
+    // The same settings with every image backend
+    for (name, encoder) in [
+        ("webp", Encoder::Libwebp),
+        ("vp8l", Encoder::Native),
+        ("png", Encoder::Png),
+        ("qoi", Encoder::Qoi),
+    ] {
+        let options = Options {
+            config: EncoderConfig {
+                encoder,
+                ..args.config
+            },
+            ..args.clone()
+        };
+        codecs.push(Codec {
+            name,
+            compress: Box::new(move |data| Ok(crate::compress(data, &options)?.to_bytes())),
+            decompress: Box::new(|data| Ok(crate::decompress(data)?)),
+        });
+    }
+    codecs
+}
+
+struct Measurement {
+    file: String,
+    codec: &'static str,
+    raw: usize,
+    compressed: usize,
+    encode_time: Duration,
+    decode_time: Duration,
+}
+
+impl Measurement {
+    fn ratio(&self) -> f64 {
+        self.compressed as f64 / self.raw.max(1) as f64
+    }
+}
+
+fn measure(file: &str, data: &[u8], codec: &Codec) -> Result<Measurement, CodecError> {
+    let start = Instant::now();
+    let compressed = (codec.compress)(data)?;
+    let encode_time = start.elapsed();
+
+    let start = Instant::now();
+    let decompressed = (codec.decompress)(&compressed)?;
+    let decode_time = start.elapsed();
+
+    if decompressed != data {
+        return Err("the decompressed file differs from the original".into());
+    }
+    Ok(Measurement {
+        file: file.to_string(),
+        codec: codec.name,
+        raw: data.len(),
+        compressed: compressed.len(),
+        encode_time,
+        decode_time,
+    })
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut output = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            c if c < ' ' => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+// Failed measurements are missing: the table shows a dash instead, the other formats skip the row
+fn print(
+    files: &[(String, usize)],
+    measurements: &[Measurement],
+    codec_names: &[&str],
+    format: Format,
+) {
+    match format {
+        Format::Table => {
+            println!(
+                "{:>24}{:>8}{:>10}{:>10}{:>8}{:>11}{:>11}",
+                "File", "Codec", "Raw", "Size", "Ratio", "Encode ms", "Decode ms"
+            );
+            for (file, raw) in files {
+                for name in codec_names {
+                    print!("{file:>24}{name:>8}{raw:>10}");
+                    match measurements
+                        .iter()
+                        .find(|m| m.file == *file && m.codec == *name)
+                    {
+                        Some(m) => println!(
+                            "{:>10}{:>8.3}{:>11.1}{:>11.1}",
+                            m.compressed,
+                            m.ratio(),
+                            m.encode_time.as_secs_f64() * 1000.0,
+                            m.decode_time.as_secs_f64() * 1000.0,
+                        ),
+                        None => println!("{:>10}{:>8}{:>11}{:>11}", "-", "-", "-", "-"),
+                    }
+                }
+            }
+        }
+        Format::Csv => {
+            println!("file,codec,raw,compressed,ratio,encode_ms,decode_ms");
+            for m in measurements {
+                println!(
+                    "{},{},{},{},{:.5},{:.3},{:.3}",
+                    csv_field(&m.file),
+                    m.codec,
+                    m.raw,
+                    m.compressed,
+                    m.ratio(),
+                    m.encode_time.as_secs_f64() * 1000.0,
+                    m.decode_time.as_secs_f64() * 1000.0,
+                );
+            }
+        }
+        Format::Json => {
+            println!("[");
+            for (i, m) in measurements.iter().enumerate() {
+                let separator = if i + 1 == measurements.len() { "" } else { "," };
+                println!(
+                    r#"  {{"file":{},"codec":"{}","raw":{},"compressed":{},"ratio":{:.5},"encode_ms":{:.3},"decode_ms":{:.3}}}{separator}"#,
+                    json_string(&m.file),
+                    m.codec,
+                    m.raw,
+                    m.compressed,
+                    m.ratio(),
+                    m.encode_time.as_secs_f64() * 1000.0,
+                    m.decode_time.as_secs_f64() * 1000.0,
+                );
+            }
+            println!("]");
+        }
+    }
+}
+
+pub fn run(corpus: &Path, format: Format, args: &Options) -> Result<(), Error> {
+    let mut paths = read_dir(corpus)?;
+    paths.retain(|path| path.is_file());
+
+    let codecs = codecs(args);
+    let mut files = Vec::new();
+    let mut measurements = Vec::new();
+    let mut failures = 0;
+    for path in paths {
+        let data = read(&path)?;
+        let file = path.file_name().unwrap().to_string_lossy().into_owned();
+        for codec in &codecs {
+            match measure(&file, &data, codec) {
+                Ok(measurement) => measurements.push(measurement),
+                Err(error) => {
+                    eprintln!("{file}: {}: {error}", codec.name);
+                    failures += 1;
+                }
+            }
+        }
+        files.push((file, data.len()));
+    }
+
+    let codec_names: Vec<&str> = codecs.iter().map(|codec| codec.name).collect();
+    print(&files, &measurements, &codec_names, format);
+    if failures > 0 {
+        eprintln!("{failures} measurements failed and are missing");
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// A dictionary of phrases shared by the pages of a site, stored once in an image that the browser
+// caches. WebP can't reference data in another image, so the dictionary can't prime the LZ77
+// window. Instead, each occurrence of a phrase in a page is replaced by ESCAPE followed by the
+// 1-based index of the phrase, and ESCAPE itself is stored as ESCAPE 0.
+//
+// Phrases are runs of markup from one `<` to the next, e.g. `<link href=../../all.css
+// rel=stylesheet>`. Boilerplate like the head, the navigation and the footer splits into such runs,
+// and so do tags that every post uses, like syntax highlighting spans.
+
+use std::collections::{HashMap, HashSet};
+
+pub const ESCAPE: u8 = 1;
+// Phrases are separated by zero bytes in the serialized dictionary
+const SEPARATOR: u8 = 0;
+const MAX_PHRASES: usize = 255;
+// A reference takes two bytes, so shorter phrases can't save anything
+const MIN_PHRASE_LEN: usize = 4;
+
+#[derive(Default)]
+pub struct Dictionary {
+    phrases: Vec<Vec<u8>>,
+    indices: HashMap<Vec<u8>, u8>,
+}
+
+// Splits the data before every `<`
+fn runs(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest[1..]
+            .iter()
+            .position(|&byte| byte == b'<')
+            .map_or(rest.len(), |i| i + 1);
+        let (run, tail) = rest.split_at(end);
+        rest = tail;
+        Some(run)
+    })
+}
+
+impl Dictionary {
+    fn new(phrases: Vec<Vec<u8>>) -> Self {
+        assert!(phrases.len() <= MAX_PHRASES, "too many phrases");
+        let indices = phrases
+            .iter()
+            .enumerate()
+            .map(|(i, phrase)| (phrase.clone(), i as u8))
+            .collect();
+        Self { phrases, indices }
+    }
+
+    // Picks the phrases that occur in at least `min_pages` pages and save the most bytes overall.
+    // The bootstrap splits the dictionary as text, so phrases have to be valid UTF-8.
+    pub fn learn(pages: &[&[u8]], min_pages: usize) -> Self {
+        // The number of pages each run occurs in and the number of occurrences
+        let mut stats: HashMap<&[u8], (usize, usize)> = HashMap::new();
+        for page in pages {
+            let mut seen = HashSet::new();
+            for run in runs(page) {
+                let (page_count, count) = stats.entry(run).or_default();
+                *count += 1;
+                if seen.insert(run) {
+                    *page_count += 1;
+                }
+            }
+        }
+        let mut candidates: Vec<(usize, &[u8])> = stats
+            .into_iter()
+            .filter(|&(run, (page_count, _))| {
+                page_count >= min_pages
+                    && run.len() >= MIN_PHRASE_LEN
+                    && !run.contains(&SEPARATOR)
+                    && !run.contains(&ESCAPE)
+                    && std::str::from_utf8(run).is_ok()
+            })
+            .map(|(run, (_, count))| (count * (run.len() - 2), run))
+            .collect();
+        // Ties are broken by the phrase so that the dictionary doesn't depend on the hash order
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+        candidates.truncate(MAX_PHRASES);
+        Self::new(
+            candidates
+                .into_iter()
+                .map(|(_, run)| run.to_vec())
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.phrases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.phrases.join(&SEPARATOR)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Self {
+        if data.is_empty() {
+            return Self::default();
+        }
+        Self::new(
+            data.split(|&byte| byte == SEPARATOR)
+                .map(|phrase| phrase.to_vec())
+                .collect(),
+        )
+    }
+
+    pub fn substitute(&self, page: &[u8]) -> Vec<u8> {
+        let mut output = Vec::with_capacity(page.len());
+        for run in runs(page) {
+            if let Some(&index) = self.indices.get(run) {
+                output.extend([ESCAPE, index + 1]);
+                continue;
+            }
+            for &byte in run {
+                output.push(byte);
+                if byte == ESCAPE {
+                    output.push(0);
+                }
+            }
+        }
+        output
+    }
+
+    // Returns None if the data refers to phrases that don't exist
+    pub fn expand(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut output = Vec::with_capacity(data.len() * 2);
+        let mut bytes = data.iter();
+        while let Some(&byte) = bytes.next() {
+            if byte != ESCAPE {
+                output.push(byte);
+                continue;
+            }
+            match *bytes.next()? {
+                0 => output.push(ESCAPE),
+                index => output.extend(self.phrases.get(index as usize - 1)?),
+            }
+        }
+        Some(output)
+    }
+}
@@ -0,0 +1,470 @@
+// Turns an HTML page into a self-decompressing one: the part above `<cut></cut>` is kept as is, so
+// that the top of the page renders immediately, and the whole page is stored in WebP images that a
+// small script decodes with WebGL. The original page is kept as a fallback for browsers without
+// JavaScript or with noisy canvas reads.
+//
+// To build the post, run `npm run build` in blog/, then `cargo run --release -- embed ../index.html`
+// here.
+
+use crate::encoding::{base122, base64, base85, Encoding, BASE122_ILLEGAL, BASE85_ALPHABET};
+use crate::error::{read_to_string, write};
+use crate::header::{CHECKSUM_OFFSET, HEADER_LEN, LENGTH_OFFSET};
+use crate::layout::Layout;
+use crate::progressive::{self, MARKER_ID};
+use crate::transform::Transform;
+use crate::{compress, Compressed, Error};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const CUT_MARKER: &str = "<cut></cut>";
+
+// Where the i-th data byte is in the RGBA array p: `i / step * scale + i % step * spread` bytes
+// after the header. The step of the planes layout is the length of a plane, a third of the data.
+// The bootstrap and simulate.rs both compute the offsets from this.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteOffsets {
+    pub step: Option<usize>,
+    pub scale: usize,
+    pub spread: usize,
+}
+
+impl ByteOffsets {
+    pub fn of(layout: Layout) -> Self {
+        let (step, scale, spread) = match layout {
+            Layout::Grayscale => (Some(1), 4, 0),
+            Layout::Rgb => (Some(3), 4, 1),
+            Layout::Rgba => (Some(1), 1, 0),
+            Layout::Planes => (None, 1, 4),
+        };
+        Self {
+            step,
+            scale,
+            spread,
+        }
+    }
+
+    // The offset of the i-th of `length` data bytes
+    pub fn offset(self, i: usize, length: usize) -> usize {
+        let step = self.step.unwrap_or(length.div_ceil(3));
+        HEADER_LEN * 4 + i / step * self.scale + i % step * self.spread
+    }
+
+    // JavaScript expression for the offset, where y is the output array
+    fn to_js(self) -> String {
+        let times = |expression: &str, factor: usize| match factor {
+            1 => expression.to_string(),
+            _ => format!("{expression}*{factor}"),
+        };
+        let start = HEADER_LEN * 4;
+        match self.step {
+            Some(1) => format!("{}+{start}", times("i", self.scale)),
+            Some(step) => format!(
+                "{}+{}+{start}",
+                times(&format!("(i/{step}|0)"), self.scale),
+                times(&format!("i%{step}"), self.spread),
+            ),
+            // The remainder comes first, as it sets l
+            None => format!(
+                "{}+{}+{start}",
+                times("i%(l=Math.ceil(y.length/3))", self.spread),
+                times("(i/l|0)", self.scale),
+            ),
+        }
+    }
+}
+
+// JavaScript statement that inverts the transform in place of the Uint8Array y, mirroring
+// transform.rs
+fn inverse_transform(transform: Transform) -> &'static str {
+    match transform {
+        Transform::Bwt => "{let I=y[0]|y[1]<<8|y[2]<<16|y[3]<<24,b=y.subarray(4),n=b.length,C=new Uint32Array(257),T=new Uint32Array(n),o=new Uint8Array(n),j=I;for(i=0;i<n;i++)C[b[i]+1]++;for(i=0;i<256;i++)C[i+1]+=C[i];for(i=0;i<n;i++)T[i]=C[b[i]]++;for(i=n;i--;j=T[j])o[i]=b[j];y=o}",
+        Transform::Mtf => "{let m=[...Array(256).keys()];y=y.map(v=>(v=m.splice(v,1)[0],m.unshift(v),v))}",
+        Transform::Delta => "for(i=1;i<y.length;i++)y[i]+=y[i-1]",
+        Transform::LineAlign => "{let L=y[0]|y[1]<<8,O=y[2]|y[3]<<8,n=y[4]|y[5]<<8|y[6]<<16|y[7]<<24,o=new Uint8Array(n),k=0,r=8+(L-(O+8)%L)%L,j;for(;k<n;r+=L)for(j=r;j<r+L&&k<n&&(o[k++]=y[j++])!=10;);y=o}",
+        Transform::SegmentAlign => "{let G=a=>y[a]|y[a+1]<<8|y[a+2]<<16|y[a+3]<<24,B=G(0),O=G(4),n=G(8),q=12+4*n,o=[],j;for(j=0;j<n;j++)q+=(B-(O+q)%B)%B,o.push(y.subarray(q,q+=G(12+4*j)));y=new Uint8Array(await new Blob(o).arrayBuffer())}",
+    }
+}
+
+// Whether the bootstrap lets createImageBitmap premultiply alpha, which is the default. That would
+// destroy RGB under transparent pixels, which only the RGBA layout has.
+pub fn premultiplies_alpha(layout: Layout) -> bool {
+    layout != Layout::Rgba
+}
+
+// JavaScript statement that feeds the bytes of y into the running CRC32 z, see crc32fast
+const UPDATE_CRC: &str = "for(i=0;i<y.length;i++)for(z^=y[i],k=8;k--;)z=z>>>1^0xEDB88320&-(z&1)";
+
+// JavaScript statement that expands the dictionary phrases in y, mirroring dictionary.rs. D is the
+// array of phrases.
+const EXPAND_PHRASES: &str = "{let o=[],v;for(i=0;i<y.length;i++)if(y[i]-1)o.push(y[i]);else if(v=y[++i])for(k of D[v-1])o.push(k);else o.push(1);y=new Uint8Array(o)}";
+
+pub struct Options<'a> {
+    pub layout: Layout,
+    pub transforms: &'a [Transform],
+    pub repair: bool,
+    pub nojs_url: &'a str,
+    // The shared dictionary image the page was substituted with, see dictionary.rs
+    pub dictionary_url: Option<&'a str>,
+    // Not Auto, which `embed_page` resolves
+    pub encoding: Encoding,
+    // The file with the images, for the external encoding
+    pub external_url: Option<&'a str>,
+}
+
+// JavaScript declarations of F, which takes the expression stored for an image and returns a
+// promise of its blob. For the external encoding, the expression is the range of the image in the
+// file, which is fetched once.
+fn loader(encoding: Encoding, external_url: Option<&str>) -> Result<String, Error> {
+    Ok(match encoding {
+        Encoding::Base64 => "F=async u=>(await fetch(u)).blob()".to_string(),
+        Encoding::Base85 => format!("A=`{BASE85_ALPHABET}`,F=async u=>{{let o=[],n,j,q;for(q=0;q<u.length;q+=5){{for(n=j=0;j<5;j++)n=n*85+(q+j<u.length?A.indexOf(u[q+j]):84);for(j=0;j<4&&q+j+1<u.length;j++)o.push(n>>>24-8*j&255)}}return new Blob([new Uint8Array(o)])}}"),
+        Encoding::Base122 => {
+            let illegal: Vec<String> = BASE122_ILLEGAL.iter().map(u8::to_string).collect();
+            format!("F=async u=>{{let o=[],a=0,n=0,I=[{}],P=x=>{{a=a<<7|x;n+=7;if(n>7)o.push(a>>(n-=8)&255)}},c,v;for(c of u)(c=c.charCodeAt())>127?((v=c>>8&7)<7&&P(I[v]),P(c&127)):P(c);return new Blob([new Uint8Array(o)])}}", illegal.join(","))
+        }
+        Encoding::External => {
+            let url = external_url.ok_or(Error::MissingExternalUrl)?;
+            format!("B,F=async u=>(await(B||=fetch(`{url}`).then(r=>r.blob()))).slice(...u)")
+        }
+        Encoding::Auto => return Err(Error::UnresolvedEncoding),
+    })
+}
+
+// The script that decodes the images, one per tile, and replaces the document with the result.
+// The numbers are WebGL constants: TEXTURE_2D, RGBA, UNSIGNED_BYTE, FRAMEBUFFER and
+// COLOR_ATTACHMENT0. The length and the checksum are read from the header, see header.rs. If the
+// checksum doesn't match, e.g. because the browser adds noise to canvas reads to prevent
+// fingerprinting, the script falls back to the original page.
+//
+// With `repair`, each grayscale byte is decided by a majority vote over R, G and B, which fixes
+// noise in a single channel.
+//
+// The dictionary is always fetched by its URL, so the browser caches it across pages. It is
+// decoded and checked first, then split into phrases.
+//
+// `chunks` has the images of each chunk as JavaScript expressions for `loader`, see progressive.rs.
+// Each chunk is decoded and checked on its own: the first one replaces the document, and the
+// others are inserted before the marker as soon as they are ready.
+pub fn bootstrap(chunks: &[Vec<String>], options: &Options) -> Result<String, Error> {
+    let Options {
+        layout,
+        transforms,
+        repair,
+        nojs_url,
+        dictionary_url,
+        encoding,
+        external_url,
+    } = *options;
+    check_repair(repair, layout)?;
+    let chunks: Vec<String> = chunks
+        .iter()
+        .map(|images| format!("[{}]", images.join(",")))
+        .collect();
+    let bitmap_options = match premultiplies_alpha(layout) {
+        true => "",
+        false => r#",{premultiplyAlpha:"none"}"#,
+    };
+    let (read, offset) = if repair {
+        (
+            ",g=o=>p[o]==p[o+1]|p[o]==p[o+2]?p[o]:p[o+1]",
+            format!("g({})", ByteOffsets::of(layout).to_js()),
+        )
+    } else {
+        ("", format!("p[{}]", ByteOffsets::of(layout).to_js()))
+    };
+    let header_byte = |i: usize| {
+        if repair {
+            format!("g({})", i * 4)
+        } else {
+            format!("p[{}]", i * 4)
+        }
+    };
+    let header_u32 = |start: usize| {
+        format!(
+            "{}|{}<<8|{}<<16|{}<<24",
+            header_byte(start),
+            header_byte(start + 1),
+            header_byte(start + 2),
+            header_byte(start + 3),
+        )
+    };
+    let length = header_u32(LENGTH_OFFSET);
+    let checksum = header_u32(CHECKSUM_OFFSET);
+    let dictionary = match dictionary_url {
+        Some(url) => format!(
+            "\nlet D=await f(fetch(`{url}`).then(r=>r.blob()))\n{{let y=D;z=-1\n{UPDATE_CRC}\nif(~z!=e)throw 0}}\nD=new TextDecoder().decode(D).split(\"\\0\").map(s=>new TextEncoder().encode(s))"
+        ),
+        None => String::new(),
+    };
+    // Without transforms and a dictionary, tiles are decoded as they arrive. Otherwise they have
+    // to be concatenated first.
+    let (init, add_tile, finish) = if transforms.is_empty() && dictionary_url.is_none() {
+        (
+            r#"z=-1;let x=new TextDecoder,s="""#.to_string(),
+            format!("s+=x.decode(y,{{stream:1}})\n{UPDATE_CRC}"),
+            "if(~z!=e)throw 0\nreturn s".to_string(),
+        )
+    } else {
+        let inverses: String = transforms
+            .iter()
+            .rev()
+            .map(|&transform| format!("{}\n", inverse_transform(transform)))
+            .collect();
+        let expand = match dictionary_url {
+            Some(_) => format!("{EXPAND_PHRASES}\n"),
+            None => String::new(),
+        };
+        (
+            "let d=[]".to_string(),
+            "d.push(y)".to_string(),
+            format!("let y=new Uint8Array(await new Blob(d).arrayBuffer())\n{inverses}z=-1\n{UPDATE_CRC}\nif(~z!=e)throw 0\n{expand}return new TextDecoder().decode(y)"),
+        )
+    };
+    Ok(format!(
+        r#"<script type=module>try{{let i,l,k,e,z,m,{loader},f=async u=>{{let b=await createImageBitmap(await u{bitmap_options}),w=b.width,h=b.height,c=new OffscreenCanvas(w,h).getContext("webgl"),t=c.createTexture(),p=new Uint8Array(w*h*4),y{read}
+c.bindTexture(3553,t)
+c.texImage2D(3553,0,6408,6408,5121,b)
+c.bindFramebuffer(36160,c.createFramebuffer())
+c.framebufferTexture2D(36160,36064,3553,t,0)
+c.readPixels(0,0,w,h,6408,5121,p)
+y=new Uint8Array({length})
+e={checksum}
+for(i=0;i<y.length;i++)y[i]={offset}
+return y}}{dictionary}
+let r=async U=>{{{init}
+for(let u of U){{let y=await f(F(u))
+{add_tile}}}
+{finish}}}
+for(let U of[{chunks}]){{let s=await r(U)
+if(m)m.insertAdjacentHTML("beforebegin",s)
+else document.documentElement.innerHTML=s,m=document.getElementById("{MARKER_ID}")}}
+m&&m.remove()}}catch(e){{location.href="{nojs_url}"}}
+</script>"#,
+        chunks = chunks.join(","),
+        loader = loader(encoding, external_url)?,
+    ))
+}
+
+// The part of the page shown while the rest is decoded: everything above the cut, or just the
+// doctype if there is no cut, so that the document isn't rendered in quirks mode
+fn kept_prefix(html: &str) -> &str {
+    if let Some((before, _)) = html.split_once(CUT_MARKER) {
+        return before;
+    }
+    if html
+        .as_bytes()
+        .get(..9)
+        .is_some_and(|start| start.eq_ignore_ascii_case(b"<!doctype"))
+    {
+        if let Some(end) = html.find('>') {
+            return &html[..end + 1];
+        }
+    }
+    ""
+}
+
+// Sends browsers without JavaScript to the fallback
+const NOSCRIPT_REFRESH: &str = "<noscript><meta http-equiv=refresh content=0;url=";
+
+// Whether the page is the output of `embed` rather than an original one
+pub fn is_embedded(html: &str) -> bool {
+    html.contains(NOSCRIPT_REFRESH)
+}
+
+// Starts fetching the external images before anything else on the page, which is also how the
+// watcher recognizes pages that use them
+pub fn preload(url: &str) -> String {
+    format!("<link rel=preload href={url} as=fetch crossorigin>")
+}
+
+// Builds the self-decompressing page from the original HTML and the compressed tiles of each of
+// its chunks. For the external encoding, the file with the images is `external_file(chunks)`.
+pub fn embed(html: &str, chunks: &[Compressed], options: &Options) -> Result<String, Error> {
+    let mut offset = 0;
+    let images = chunks
+        .iter()
+        .map(|compressed| {
+            compressed
+                .tiles
+                .iter()
+                .map(|image| {
+                    let encoder =
+                        crate::backend::detect_encoder(image).ok_or(Error::UnknownFormat)?;
+                    let mime_type = encoder
+                        .backend()
+                        .mime_type()
+                        .ok_or(Error::UnsupportedByBrowsers(encoder))?;
+                    offset += image.len();
+                    Ok(match options.encoding {
+                        Encoding::Base64 => format!("`data:{mime_type};base64,{}`", base64(image)),
+                        Encoding::Base85 => format!("`{}`", base85(image)),
+                        Encoding::Base122 => format!("`{}`", base122(image)),
+                        Encoding::External => format!("[{},{offset}]", offset - image.len()),
+                        Encoding::Auto => return Err(Error::UnresolvedEncoding),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // Fetched fonts and styles would otherwise delay the images
+    let preload = match options.external_url {
+        Some(url) if options.encoding == Encoding::External => preload(url),
+        _ => String::new(),
+    };
+    // The huge div keeps the scroll position on reload until the page is decoded
+    Ok(format!(
+        "{}{preload}{NOSCRIPT_REFRESH}{}></noscript><div style=height:100000px>{}",
+        kept_prefix(html),
+        options.nojs_url,
+        bootstrap(&images, options)?,
+    ))
+}
+
+// The images of all chunks back to back, as the external encoding addresses them
+pub fn external_file(chunks: &[Compressed]) -> Vec<u8> {
+    chunks.iter().flat_map(Compressed::to_bytes).collect()
+}
+
+pub fn gzipped_len(data: &[u8]) -> usize {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap().len()
+}
+
+// Noise can only be repaired where R, G and B are copies of each other
+pub fn check_repair(repair: bool, layout: Layout) -> Result<(), Error> {
+    match repair && layout != Layout::Grayscale {
+        true => Err(Error::UnsupportedRepair(layout)),
+        false => Ok(()),
+    }
+}
+
+pub fn check_browser_support(options: &crate::Options) -> Result<(), Error> {
+    let encoder = options.config.encoder;
+    match encoder.backend().mime_type() {
+        Some(_) => Ok(()),
+        None => Err(Error::UnsupportedByBrowsers(encoder)),
+    }
+}
+
+// The self-decompressing version of a page, with the encoding that transfers the fewest bytes
+pub struct Embedded {
+    pub page: String,
+    pub encoding: Encoding,
+    // One per chunk, in order
+    pub compressed: Vec<Compressed>,
+    // The gzipped size of the page, plus the size of the external file, which is not worth gzipping
+    pub transfer: usize,
+    // The transfer size with each encoding that was tried, the chosen one included
+    pub transfers: Vec<(Encoding, usize)>,
+    pub original_len: usize,
+}
+
+impl Embedded {
+    // What to store at the external URL, if the page uses it
+    pub fn external_file(&self) -> Option<Vec<u8>> {
+        (self.encoding == Encoding::External).then(|| external_file(&self.compressed))
+    }
+}
+
+// Compresses the page, split into up to `chunks` chunks, and embeds it with `encoding`, or with
+// each encoding in turn for auto. The fallback and the external file are linked by these URLs.
+pub fn embed_page(
+    html: &str,
+    nojs_url: &str,
+    external_url: &str,
+    repair: bool,
+    chunks: usize,
+    encoding: Encoding,
+    options: &crate::Options,
+) -> Result<Embedded, Error> {
+    check_repair(repair, options.layout)?;
+    check_browser_support(options)?;
+    let compressed = progressive::split(html, chunks)
+        .iter()
+        .map(|chunk| compress(chunk.as_bytes(), options))
+        .collect::<Result<Vec<_>, _>>()?;
+    let images_len: usize = compressed.iter().map(Compressed::len).sum();
+    let encodings = match encoding {
+        Encoding::Auto => Encoding::ALL.to_vec(),
+        encoding => vec![encoding],
+    };
+    let mut best: Option<(usize, Encoding, String)> = None;
+    let mut transfers = Vec::new();
+    for candidate in encodings {
+        let page = embed(
+            html,
+            &compressed,
+            &Options {
+                layout: options.layout,
+                transforms: &options.transforms,
+                repair,
+                nojs_url,
+                dictionary_url: None,
+                encoding: candidate,
+                external_url: Some(external_url),
+            },
+        )?;
+        let mut transfer = gzipped_len(page.as_bytes());
+        if candidate == Encoding::External {
+            transfer += images_len;
+        }
+        transfers.push((candidate, transfer));
+        if best.as_ref().is_none_or(|(size, ..)| transfer < *size) {
+            best = Some((transfer, candidate, page));
+        }
+    }
+    let (transfer, encoding, page) = best.unwrap();
+    Ok(Embedded {
+        page,
+        encoding,
+        compressed,
+        transfer,
+        transfers,
+        original_len: html.len(),
+    })
+}
+
+fn file_name(path: &Path) -> Result<&str, Error> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::InvalidFileName(path.to_path_buf()))
+}
+
+// Compresses the page at `input` with `embed_page` and writes the self-decompressing page, the
+// fallback and, if needed, the external file. Returns the page, for reporting.
+pub fn embed_file(
+    input: &Path,
+    output: Option<&Path>,
+    nojs: Option<&Path>,
+    repair: bool,
+    chunks: usize,
+    encoding: Encoding,
+    options: &crate::Options,
+) -> Result<Embedded, Error> {
+    let html = read_to_string(input)?;
+    // Embedding it again would overwrite the fallback with the bootstrap page
+    if is_embedded(&html) {
+        return Err(Error::AlreadyEmbedded(input.to_path_buf()));
+    }
+    let output = output.unwrap_or(input);
+    let nojs = nojs.map_or_else(|| output.with_file_name("nojs.html"), PathBuf::from);
+    let external = output.with_extension("webp");
+    // The fallback and the images are linked relative to the page
+    let embedded = embed_page(
+        &html,
+        file_name(&nojs)?,
+        file_name(&external)?,
+        repair,
+        chunks,
+        encoding,
+        options,
+    )?;
+
+    write(&nojs, &html)?;
+    write(output, &embedded.page)?;
+    if let Some(images) = embedded.external_file() {
+        write(&external, images)?;
+    }
+    Ok(embedded)
+}
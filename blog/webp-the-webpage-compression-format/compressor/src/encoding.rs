@@ -0,0 +1,120 @@
+// How the images are stored in the page. A base64 data URL is the obvious choice, and gzip undoes
+// most of its overhead, but not all of it: denser encodings leave less for gzip to recover, and an
+// external file avoids the text encoding altogether at the cost of a request.
+//
+// The dense encodings are stored in JavaScript template literals, so they avoid the characters that
+// would end or escape the literal (`` ` ``, `\` and `$`), that would end the script (`<`), and that
+// the HTML parser replaces (NUL and CR).
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encoding {
+    /// Data URLs, 4 characters per 3 bytes
+    Base64,
+    /// 5 ASCII characters per 4 bytes, decoded by the script
+    Base85,
+    /// One UTF-8 character per 7 bits, mostly single bytes, decoded by the script. The page has to
+    /// be served as UTF-8.
+    Base122,
+    /// A .webp file next to the page, preloaded from the page
+    External,
+    /// Try every encoding and keep the one with the smallest gzipped transfer size
+    Auto,
+}
+
+impl Encoding {
+    pub const ALL: [Self; 4] = [Self::Base64, Self::Base85, Self::Base122, Self::External];
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Base64 => "base64",
+            Self::Base85 => "base85",
+            Self::Base122 => "base122",
+            Self::External => "external",
+            Self::Auto => "auto",
+        };
+        write!(f, "{name}")
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut group = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+// Printable ASCII without the characters above, in order
+pub const BASE85_ALPHABET: &str =
+    "!\"#%&'()*+,-./0123456789:;=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[]^_abcdefghijklmnopqrstuvwxy";
+
+// Like Ascii85: each group of 4 bytes is a big-endian number written as 5 digits, and a final group
+// of n bytes is padded with zeros and written as n + 1 digits
+pub fn base85(data: &[u8]) -> String {
+    let alphabet = BASE85_ALPHABET.as_bytes();
+    let mut output = String::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        let mut group = [0; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut n = u32::from_be_bytes(group);
+        let mut digits = [0; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = alphabet[(n % 85) as usize];
+            n /= 85;
+        }
+        output.extend(digits[..chunk.len() + 1].iter().map(|&digit| digit as char));
+    }
+    output
+}
+
+// 7-bit values that can't be stored as themselves
+pub const BASE122_ILLEGAL: [u8; 6] = [0, b'\r', b'$', b'<', b'\\', b'`'];
+// The illegal index that marks an illegal value at the very end
+const BASE122_SHORTENED: u32 = 7;
+
+// Base-122, see https://blog.kevinalbertson.com/posts/base-122/: the data is split into 7-bit
+// values, zero-padded at the end, and each one becomes an ASCII character. An illegal value is
+// stored together with the next one in a two-byte UTF-8 character, U+0080 to U+07FF, whose bits are
+// the illegal index, a one, and the next value.
+pub fn base122(data: &[u8]) -> String {
+    let mut bits = data
+        .iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| byte >> i & 1));
+    let mut next = || {
+        let (mut value, mut len) = (0, 0);
+        for bit in bits.by_ref().take(7) {
+            value = value << 1 | bit;
+            len += 1;
+        }
+        (len > 0).then(|| value << (7 - len))
+    };
+    let mut output = String::with_capacity(data.len() * 8 / 7 + 1);
+    while let Some(value) = next() {
+        let Some(index) = BASE122_ILLEGAL.iter().position(|&illegal| illegal == value) else {
+            output.push(value as char);
+            continue;
+        };
+        let (index, next) = match next() {
+            Some(next) => (index as u32, next),
+            None => (BASE122_SHORTENED, value),
+        };
+        output.push(char::from_u32(index << 8 | 0x80 | next as u32).unwrap());
+    }
+    output
+}
@@ -0,0 +1,149 @@
+// Errors returned by the library. Bugs are still panics, only bad input and failing I/O or libwebp
+// are reported this way.
+
+use crate::config::Encoder;
+use crate::header::HeaderError;
+use crate::layout::Layout;
+use std::path::PathBuf;
+use webp::WebPEncodingError;
+
+#[derive(Debug)]
+pub enum Error {
+    // Compression
+    EmptyInput,
+    TooManyTransforms(usize),
+    DimensionOverflow { width: u32, height: u32 },
+    TooManyTiles(usize),
+    StreamedTransforms,
+    RowLength(usize),
+    Encode(WebPEncodingError),
+    EmptyDictionary,
+    UnsupportedByBrowsers(Encoder),
+    AlreadyEmbedded(PathBuf),
+    UnsupportedRepair(Layout),
+    DictionaryRoundTrip(PathBuf),
+    InvalidFileName(PathBuf),
+    UnresolvedEncoding,
+    MissingExternalUrl,
+    // Decompression
+    UnknownFormat,
+    Decode,
+    Header(HeaderError),
+    Truncated,
+    MissingTile { tile: u16, tiles: u16 },
+    InconsistentTiles,
+    MalformedTransform,
+    ChecksumMismatch,
+    Io(PathBuf, std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::EmptyInput => write!(f, "the input is empty"),
+            Self::TooManyTransforms(count) => write!(
+                f,
+                "{count} transforms given, at most {} are supported",
+                crate::transform::MAX_TRANSFORMS
+            ),
+            Self::DimensionOverflow { width, height } => write!(
+                f,
+                "a {width}x{height} image is out of range, WebP supports 1 to {} pixels in each dimension",
+                crate::width::MAX_DIMENSION
+            ),
+            Self::TooManyTiles(tiles) => write!(
+                f,
+                "the input needs {tiles} tiles, which is too large even for tiling"
+            ),
+            Self::StreamedTransforms => {
+                write!(f, "transforms need the whole input, so it can't be streamed")
+            }
+            Self::RowLength(row_len) => write!(
+                f,
+                "aligned transforms need rows of 1 to {} bytes, not {row_len}",
+                u16::MAX
+            ),
+            Self::Encode(error) => write!(f, "libwebp failed to encode the image: {error:?}"),
+            Self::EmptyDictionary => write!(
+                f,
+                "no phrase occurs in enough pages to build a dictionary"
+            ),
+            Self::UnsupportedByBrowsers(encoder) => {
+                write!(f, "browsers can't decode images from the {encoder:?} encoder")
+            }
+            Self::AlreadyEmbedded(path) => write!(
+                f,
+                "{} is already a self-decompressing page, embed its original instead",
+                path.display()
+            ),
+            Self::UnresolvedEncoding => {
+                write!(f, "the auto encoding has to be resolved to one encoding first")
+            }
+            Self::MissingExternalUrl => {
+                write!(f, "the external encoding needs the URL of the file with the images")
+            }
+            Self::UnsupportedRepair(layout) => write!(
+                f,
+                "--repair is only supported by the grayscale layout, not {layout:?}"
+            ),
+            Self::DictionaryRoundTrip(path) => write!(
+                f,
+                "{} doesn't round-trip through the dictionary",
+                path.display()
+            ),
+            Self::InvalidFileName(path) => write!(
+                f,
+                "{} has no UTF-8 file name for the page to link to",
+                path.display()
+            ),
+            Self::UnknownFormat => write!(f, "the input is not a sequence of supported images"),
+            Self::Decode => write!(f, "failed to decode the image"),
+            Self::Header(error) => write!(f, "{error}"),
+            Self::Truncated => write!(f, "the image is too small for the length in the header"),
+            Self::MissingTile { tile, tiles } => write!(f, "tile {} of {tiles} is missing", tile + 1),
+            Self::InconsistentTiles => write!(f, "the tiles disagree on the transforms"),
+            Self::MalformedTransform => write!(f, "the transformed data is malformed"),
+            Self::ChecksumMismatch => write!(f, "checksum mismatch, the image is corrupted"),
+            Self::Io(path, error) => write!(f, "{}: {error}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Header(error) => Some(error),
+            Self::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<HeaderError> for Error {
+    fn from(error: HeaderError) -> Self {
+        Self::Header(error)
+    }
+}
+
+pub fn read(path: &std::path::Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|error| Error::Io(path.to_path_buf(), error))
+}
+
+pub fn write(path: &std::path::Path, data: impl AsRef<[u8]>) -> Result<(), Error> {
+    std::fs::write(path, data).map_err(|error| Error::Io(path.to_path_buf(), error))
+}
+
+pub fn read_to_string(path: &std::path::Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|error| Error::Io(path.to_path_buf(), error))
+}
+
+// The entries of a directory, sorted
+pub fn read_dir(path: &std::path::Path) -> Result<Vec<PathBuf>, Error> {
+    let io_error = |error| Error::Io(path.to_path_buf(), error);
+    let mut paths = std::fs::read_dir(path)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()).map_err(io_error))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+    Ok(paths)
+}
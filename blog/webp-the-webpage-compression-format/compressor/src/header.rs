@@ -0,0 +1,100 @@
+// The first pixels of every image hold a header describing how to decode the rest, so that the
+// decoder needs nothing but the image. Each header byte takes one pixel, like grayscale data.
+
+use crate::layout::Layout;
+use crate::transform::{Transform, MAX_TRANSFORMS};
+use std::ops::Range;
+
+pub const MAGIC: [u8; 3] = *b"WPC";
+pub const VERSION: u8 = 6;
+
+// Magic, version, layout, little-endian 32-bit length, 16-bit tile index and tile count, the
+// transforms in the order they were applied, padded with zeros, and the 32-bit checksum. The
+// bootstrap reads the length and the checksum at their offsets.
+pub const LENGTH_OFFSET: usize = 5;
+const TRANSFORMS: Range<usize> = 13..13 + MAX_TRANSFORMS;
+pub const CHECKSUM_OFFSET: usize = TRANSFORMS.end;
+pub const HEADER_LEN: usize = CHECKSUM_OFFSET + 4;
+
+#[derive(Debug)]
+pub enum HeaderError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownLayout(u8),
+    UnknownTransform(u8),
+    Truncated,
+}
+
+impl std::fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "the image was not produced by compressor"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported format version {version}"),
+            Self::UnknownLayout(layout) => write!(f, "unknown layout {layout}"),
+            Self::UnknownTransform(transform) => write!(f, "unknown transform {transform}"),
+            Self::Truncated => write!(f, "the image is too small to hold the header"),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    // How data bytes are mapped to pixels
+    pub layout: Layout,
+    // Length of the data in this tile in bytes
+    pub length: u32,
+    // Large inputs are split into several images, each with its own header
+    pub tile: u16,
+    pub tiles: u16,
+    // Applied to the whole input before splitting it into tiles, at most MAX_TRANSFORMS
+    pub transforms: Vec<Transform>,
+    // CRC32 of the original input up to the end of this tile, so that the last tile holds that of
+    // the whole input and corruption by canvas noise can be detected. Transformed tiles don't end
+    // at a point in the input, so they all hold the checksum of the whole input.
+    pub checksum: u32,
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[..3].copy_from_slice(&MAGIC);
+        bytes[3] = VERSION;
+        bytes[4] = self.layout as u8;
+        bytes[LENGTH_OFFSET..LENGTH_OFFSET + 4].copy_from_slice(&self.length.to_le_bytes());
+        bytes[9..11].copy_from_slice(&self.tile.to_le_bytes());
+        bytes[11..13].copy_from_slice(&self.tiles.to_le_bytes());
+        for (byte, &transform) in bytes[TRANSFORMS].iter_mut().zip(&self.transforms) {
+            *byte = transform as u8;
+        }
+        bytes[CHECKSUM_OFFSET..].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, HeaderError> {
+        let bytes: &[u8; HEADER_LEN] = bytes
+            .get(..HEADER_LEN)
+            .ok_or(HeaderError::Truncated)?
+            .try_into()
+            .unwrap();
+        if bytes[..3] != MAGIC {
+            return Err(HeaderError::BadMagic);
+        }
+        if bytes[3] != VERSION {
+            return Err(HeaderError::UnsupportedVersion(bytes[3]));
+        }
+        Ok(Self {
+            layout: Layout::from_byte(bytes[4]).ok_or(HeaderError::UnknownLayout(bytes[4]))?,
+            length: u32::from_le_bytes(bytes[LENGTH_OFFSET..LENGTH_OFFSET + 4].try_into().unwrap()),
+            tile: u16::from_le_bytes(bytes[9..11].try_into().unwrap()),
+            tiles: u16::from_le_bytes(bytes[11..13].try_into().unwrap()),
+            transforms: bytes[TRANSFORMS]
+                .iter()
+                .take_while(|&&byte| byte != 0)
+                .map(|&byte| Transform::from_byte(byte).ok_or(HeaderError::UnknownTransform(byte)))
+                .collect::<Result<_, _>>()?,
+            checksum: u32::from_le_bytes(bytes[CHECKSUM_OFFSET..].try_into().unwrap()),
+        })
+    }
+}
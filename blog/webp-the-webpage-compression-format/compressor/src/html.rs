@@ -0,0 +1,295 @@
+// A lightweight HTML tokenizer and a model of the stack of open elements, just precise enough to tell
+// where elements start and end in the minified pages the blog generates. Those leave out optional
+// end tags, like `</p>` and `</li>`, and attribute quotes, so both have to be handled the way a
+// browser would. Tables, forms and misnested formatting elements are not modelled: callers only
+// trust positions outside of them.
+
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+    Start { name: &'a str, self_closing: bool },
+    End { name: &'a str },
+    Text,
+    Comment,
+    Doctype,
+}
+
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+// Their contents are text up to the matching end tag, except in svg or math
+const RAW_TEXT_ELEMENTS: [&str; 5] = ["script", "style", "textarea", "title", "xmp"];
+// Start tags that close an open `<p>`
+const CLOSES_P: [&str; 36] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "ul",
+];
+// Elements that stop the search for an element to close implicitly
+const SCOPE_BOUNDARIES: [&str; 11] = [
+    "applet", "button", "caption", "html", "marquee", "math", "object", "svg", "table", "td",
+    "template",
+];
+
+fn is_foreign(name: &str) -> bool {
+    name.eq_ignore_ascii_case("svg") || name.eq_ignore_ascii_case("math")
+}
+
+pub struct Tokenizer<'a> {
+    html: &'a str,
+    position: usize,
+    // Set after the start tag of a raw text element, to its name in lowercase
+    raw_text: Option<&'static str>,
+    // How many svg and math elements are open
+    foreign: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(html: &'a str) -> Self {
+        Self {
+            html,
+            position: 0,
+            raw_text: None,
+            foreign: 0,
+        }
+    }
+
+    // Where the raw text that starts at the current position ends: at the first end tag of the
+    // element, in any case, or at the end
+    fn raw_text_end(&self, name: &str) -> usize {
+        let mut end = self.position;
+        while let Some(i) = self.html[end..].find("</") {
+            end += i + 2;
+            let tag = self.html.as_bytes()[end..].get(..name.len());
+            if tag.is_some_and(|tag| tag.eq_ignore_ascii_case(name.as_bytes())) {
+                return end - 2;
+            }
+        }
+        self.html.len()
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.html.as_bytes()[self.position..]
+    }
+
+    // Skips to just after `needle`, or to the end
+    fn skip_past(&mut self, needle: &str) {
+        self.position = match self.html[self.position..].find(needle) {
+            Some(i) => self.position + i + needle.len(),
+            None => self.html.len(),
+        };
+    }
+
+    fn name(&mut self) -> &'a str {
+        let start = self.position;
+        let len = self
+            .rest()
+            .iter()
+            .position(|&c| c.is_ascii_whitespace() || c == b'/' || c == b'>')
+            .unwrap_or(self.rest().len());
+        self.position += len;
+        &self.html[start..self.position]
+    }
+
+    // Skips attributes, which may contain `>` in quoted values. Returns whether the tag ends with
+    // `/>`.
+    fn attributes(&mut self) -> bool {
+        let mut self_closing = false;
+        while let Some(&c) = self.rest().first() {
+            self.position += 1;
+            match c {
+                b'>' => return self_closing,
+                b'/' => {
+                    self_closing = true;
+                    continue;
+                }
+                b'"' | b'\'' => self.skip_past(if c == b'"' { "\"" } else { "'" }),
+                _ => {}
+            }
+            self_closing = false;
+        }
+        self_closing
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (Range<usize>, Token<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        if start == self.html.len() {
+            return None;
+        }
+        if let Some(name) = self.raw_text.take() {
+            let end = self.raw_text_end(name);
+            if end > start {
+                self.position = end;
+                return Some((start..end, Token::Text));
+            }
+        }
+
+        let rest = self.rest();
+        let token = if rest.starts_with(b"<!--") {
+            self.position += 4;
+            self.skip_past("-->");
+            Token::Comment
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"<?") {
+            self.skip_past(">");
+            Token::Doctype
+        } else if rest.len() > 1 && rest[0] == b'<' && rest[1].is_ascii_alphabetic() {
+            self.position += 1;
+            let name = self.name();
+            let self_closing = self.attributes();
+            if is_foreign(name) && !self_closing {
+                self.foreign += 1;
+            } else if self.foreign == 0 {
+                self.raw_text = RAW_TEXT_ELEMENTS
+                    .into_iter()
+                    .find(|raw| name.eq_ignore_ascii_case(raw));
+            }
+            Token::Start { name, self_closing }
+        } else if rest.len() > 2 && rest.starts_with(b"</") && rest[2].is_ascii_alphabetic() {
+            self.position += 2;
+            let name = self.name();
+            self.skip_past(">");
+            if is_foreign(name) {
+                self.foreign = self.foreign.saturating_sub(1);
+            }
+            Token::End { name }
+        } else {
+            // Text up to the next tag; a lone `<` is text too
+            let len = rest[1..]
+                .iter()
+                .position(|&c| c == b'<')
+                .map_or(rest.len(), |i| i + 1);
+            self.position += len;
+            Token::Text
+        };
+        Some((start..self.position, token))
+    }
+}
+
+// An open element. Elements are numbered in document order, so that two elements with the same
+// name can be told apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Element<'a> {
+    pub name: &'a str,
+    pub id: usize,
+}
+
+#[derive(Default)]
+pub struct Tree<'a> {
+    pub stack: Vec<Element<'a>>,
+    // Inside svg or math, `/>` closes elements and HTML rules don't apply
+    foreign: usize,
+    next_id: usize,
+}
+
+impl<'a> Tree<'a> {
+    // The index of the topmost open element named `name` that can be closed implicitly, if any
+    fn in_scope(&self, name: &str, also_stop_at: &[&str]) -> Option<usize> {
+        for (i, element) in self.stack.iter().enumerate().rev() {
+            if element.name == name {
+                return Some(i);
+            }
+            if SCOPE_BOUNDARIES.contains(&element.name) || also_stop_at.contains(&element.name) {
+                return None;
+            }
+        }
+        None
+    }
+
+    // How many elements a start tag closes implicitly, counted from the top of the stack
+    pub fn implied_closes(&self, name: &str) -> usize {
+        if self.foreign > 0 {
+            return 0;
+        }
+        let mut depth = self.stack.len();
+        let mut close = |index: Option<usize>| {
+            if let Some(index) = index {
+                depth = depth.min(index);
+            }
+        };
+        match name {
+            "li" => close(self.in_scope("li", &["ol", "ul"])),
+            "dd" | "dt" => {
+                close(self.in_scope("dd", &["dl"]));
+                close(self.in_scope("dt", &["dl"]));
+            }
+            _ => {}
+        }
+        if CLOSES_P.contains(&name) {
+            close(self.in_scope("p", &[]));
+        }
+        self.stack.len() - depth
+    }
+
+    pub fn process(&mut self, token: Token<'a>) {
+        match token {
+            Token::Start { name, self_closing } => {
+                let name_is_foreign = name == "svg" || name == "math";
+                let closes = self.implied_closes(name);
+                self.stack.truncate(self.stack.len() - closes);
+                if VOID_ELEMENTS.contains(&name) && self.foreign == 0
+                    || self_closing && (self.foreign > 0 || name_is_foreign)
+                {
+                    return;
+                }
+                if self.foreign > 0 || name_is_foreign {
+                    self.foreign += 1;
+                }
+                self.stack.push(Element {
+                    name,
+                    id: self.next_id,
+                });
+                self.next_id += 1;
+            }
+            Token::End { name } => {
+                // Content after these still goes into the body
+                if matches!(name, "html" | "head" | "body") {
+                    return;
+                }
+                if let Some(index) = self.stack.iter().rposition(|element| element.name == name) {
+                    self.foreign = self.foreign.saturating_sub(self.stack.len() - index);
+                    self.stack.truncate(index);
+                }
+            }
+            Token::Text | Token::Comment | Token::Doctype => {}
+        }
+    }
+}
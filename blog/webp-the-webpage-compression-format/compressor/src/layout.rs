@@ -0,0 +1,106 @@
+// Ways to map data bytes to RGBA pixels. The header always uses one byte per pixel, so that it can
+// be read before the layout is known.
+
+pub type Pixel = [u8; 4];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Layout {
+    /// One byte per pixel, copied to R, G and B; the subtract-green transform makes G and B free
+    Grayscale = 0,
+    /// Three consecutive bytes per pixel, in R, G and B
+    Rgb = 1,
+    /// Four consecutive bytes per pixel, including alpha. Requires exact encoding, and browsers may
+    /// premultiply alpha on decoding, so this is only reliable outside the browser.
+    Rgba = 2,
+    /// The data is split into three equal parts, stored in the R, G and B planes respectively
+    Planes = 3,
+}
+
+impl Layout {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Grayscale),
+            1 => Some(Self::Rgb),
+            2 => Some(Self::Rgba),
+            3 => Some(Self::Planes),
+            _ => None,
+        }
+    }
+
+    // How many consecutive input bytes end up in one pixel. A line of this many times the width
+    // bytes occupies exactly one row.
+    pub fn stride(self) -> usize {
+        match self {
+            Self::Grayscale | Self::Planes => 1,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+
+    pub fn pixels(self, length: usize) -> usize {
+        match self {
+            Self::Grayscale => length,
+            Self::Rgb | Self::Planes => length.div_ceil(3),
+            Self::Rgba => length.div_ceil(4),
+        }
+    }
+
+    // How many bytes fit in this many pixels
+    pub fn capacity(self, pixels: usize) -> usize {
+        match self {
+            Self::Grayscale => pixels,
+            Self::Rgb | Self::Planes => pixels * 3,
+            Self::Rgba => pixels * 4,
+        }
+    }
+
+    // The i-th pixel of the packed data
+    pub fn pixel(self, data: &[u8], i: usize) -> Pixel {
+        let byte = |index: usize| data.get(index).copied().unwrap_or(0);
+        match self {
+            Self::Grayscale => [data[i], data[i], data[i], 255],
+            Self::Rgb => [byte(i * 3), byte(i * 3 + 1), byte(i * 3 + 2), 255],
+            // A partial last pixel stays opaque
+            Self::Rgba => [
+                byte(i * 4),
+                byte(i * 4 + 1),
+                byte(i * 4 + 2),
+                data.get(i * 4 + 3).copied().unwrap_or(255),
+            ],
+            Self::Planes => {
+                let plane_len = self.pixels(data.len());
+                [byte(i), byte(plane_len + i), byte(plane_len * 2 + i), 255]
+            }
+        }
+    }
+
+    pub fn pack(self, data: &[u8]) -> Vec<Pixel> {
+        (0..self.pixels(data.len()))
+            .map(|i| self.pixel(data, i))
+            .collect()
+    }
+
+    // Returns None if there are too few pixels
+    pub fn unpack(self, pixels: &[Pixel], length: usize) -> Option<Vec<u8>> {
+        let pixels = pixels.get(..self.pixels(length))?;
+        let mut data: Vec<u8> = match self {
+            // R, G and B are copies, so a majority vote repairs noise in one of them
+            Self::Grayscale => pixels
+                .iter()
+                .map(|&[r, g, b, _]| if r == g || r == b { r } else { g })
+                .collect(),
+            Self::Rgb => pixels
+                .iter()
+                .flat_map(|pixel| &pixel[..3])
+                .copied()
+                .collect(),
+            Self::Rgba => pixels.iter().flatten().copied().collect(),
+            Self::Planes => (0..3)
+                .flat_map(|index| pixels.iter().map(move |pixel| pixel[index]))
+                .collect(),
+        };
+        // Only the last pixel, or the end of the last plane, is padded
+        data.truncate(length);
+        Some(data)
+    }
+}
@@ -0,0 +1,416 @@
+// Compresses data into lossless WebP images that browsers can decode with WebGL, and back. Each
+// image starts with a header describing how to decode it, see header.rs, so `decompress` needs
+// nothing but the output of `compress`.
+
+use clap::Args;
+use config::EncoderConfig;
+use header::{Header, HEADER_LEN};
+use layout::{Layout, Pixel};
+use pixels::Pixels;
+use std::io::{Read, Seek, SeekFrom, Write};
+use transform::{Geometry, Transform, BLOCK_ROWS, MAX_TRANSFORMS};
+use width::Width;
+
+pub mod backend;
+pub mod bench;
+pub mod config;
+pub mod dictionary;
+pub mod embed;
+pub mod encoding;
+mod error;
+pub mod header;
+pub mod html;
+pub mod layout;
+pub mod pixels;
+pub mod progressive;
+pub mod segment;
+pub mod simulate;
+pub mod site;
+pub mod transform;
+mod vp8l;
+pub mod watch;
+pub mod width;
+
+pub use error::Error;
+
+#[derive(Args, Clone, Debug)]
+pub struct Options {
+    /// Image width in pixels, or "auto" to try several widths and keep the smallest output
+    #[arg(long)]
+    pub width: Option<Width>,
+    /// How data bytes are mapped to pixels
+    #[arg(long, value_enum, default_value_t = Layout::Grayscale)]
+    pub layout: Layout,
+    /// Reversible transforms applied before encoding, in order, separated by commas
+    #[arg(long = "transform", value_enum, value_delimiter = ',')]
+    pub transforms: Vec<Transform>,
+    #[command(flatten)]
+    pub config: EncoderConfig,
+    /// Try several encoder configurations and keep the smallest output
+    #[arg(long)]
+    pub max_effort: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: None,
+            layout: Layout::Grayscale,
+            transforms: Vec::new(),
+            config: EncoderConfig::default(),
+            max_effort: false,
+        }
+    }
+}
+
+// The width and encoder configuration an image was encoded with, which may have been picked from
+// several candidates
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub width: u32,
+    pub config: EncoderConfig,
+}
+
+// One WebP image per tile, in order. Stored back to back, they are what `decompress` takes.
+pub struct Compressed {
+    pub tiles: Vec<Vec<u8>>,
+    // One per tile
+    pub settings: Vec<Settings>,
+}
+
+impl Compressed {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.tiles.concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.iter().map(|tile| tile.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+}
+
+// Encodes the pixels, padding the image to a rectangle
+fn encode(pixels: &Pixels, width: u32, config: &EncoderConfig) -> Result<Vec<u8>, Error> {
+    let height = (pixels.count() as u32).div_ceil(width.max(1));
+    if !(1..=width::MAX_DIMENSION).contains(&width) || height > width::MAX_DIMENSION {
+        return Err(Error::DimensionOverflow { width, height });
+    }
+    config
+        .encoder
+        .backend()
+        .encode(pixels, width, height, config)
+}
+
+// Encodes with every candidate width and configuration, a few at a time, and keeps the smallest
+// output
+fn encode_best(
+    pixels: &Pixels,
+    candidates: &[(u32, EncoderConfig)],
+) -> Result<(Vec<u8>, Settings), Error> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    for (batch_index, batch) in candidates.chunks(threads).enumerate() {
+        let encoded: Vec<Result<Vec<u8>, Error>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .iter()
+                .map(|(width, config)| scope.spawn(move || encode(pixels, *width, config)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        for (i, webp) in encoded.into_iter().enumerate() {
+            let webp = webp?;
+            if best
+                .as_ref()
+                .is_none_or(|(_, best)| webp.len() < best.len())
+            {
+                best = Some((batch_index * threads + i, webp));
+            }
+        }
+    }
+    // Only widths that fit the image are candidates, so there are none if even the widest doesn't
+    let Some((index, webp)) = best else {
+        return Err(Error::DimensionOverflow {
+            width: width::MAX_DIMENSION,
+            height: (pixels.count() as u32).div_ceil(width::MAX_DIMENSION),
+        });
+    };
+    let (width, config) = candidates[index];
+    Ok((webp, Settings { width, config }))
+}
+
+fn compress_tile(
+    binary_data: &[u8],
+    header: &Header,
+    width: Option<Width>,
+    options: &Options,
+) -> Result<(Vec<u8>, Settings), Error> {
+    let pixels = Pixels::new(header, binary_data);
+    let pixel_count = pixels.count() as u32;
+
+    let widths = match width {
+        None => vec![width::default_width(pixel_count)],
+        Some(Width::Fixed(width)) => vec![width],
+        Some(Width::Auto) => width::candidates(binary_data, pixel_count, options.layout.stride()),
+    };
+
+    let mut config = options.config;
+    config.exact |= options.layout == Layout::Rgba;
+    let configs = if options.max_effort {
+        config.max_effort_variants()
+    } else {
+        vec![config]
+    };
+
+    let candidates: Vec<(u32, EncoderConfig)> = widths
+        .iter()
+        .flat_map(|&width| configs.iter().map(move |&config| (width, config)))
+        .collect();
+    match candidates[..] {
+        [(width, config)] => Ok((encode(&pixels, width, &config)?, Settings { width, config })),
+        _ => encode_best(&pixels, &candidates),
+    }
+}
+
+// How many bytes of the input go into each tile, so that each one is small enough to be encoded as
+// one image of at most `max_pixels`
+fn tile_len(width: Option<Width>, max_pixels: u32, options: &Options) -> usize {
+    let (max_pixels, row_len) = match width {
+        Some(Width::Fixed(width)) => (
+            max_pixels.min(width * width::MAX_DIMENSION),
+            options.layout.capacity(width as usize),
+        ),
+        _ => (max_pixels, 1),
+    };
+    let tile_len = options.layout.capacity(max_pixels as usize - HEADER_LEN);
+    // Keep rows aligned across tiles, and rows of blocks for segments
+    let align = if options.transforms.contains(&Transform::SegmentAlign) {
+        row_len * BLOCK_ROWS
+    } else {
+        row_len
+    };
+    tile_len - tile_len % align
+}
+
+fn tile_count(len: usize, tile_len: usize) -> Result<u16, Error> {
+    let count = len.div_ceil(tile_len);
+    count.try_into().map_err(|_| Error::TooManyTiles(count))
+}
+
+// Splits the transformed input into tiles. The images are simply concatenated, as each format
+// records its own length one way or another. Without a checksum of the whole input, the data is the
+// input itself, and each tile gets the checksum of the input up to its end, see header.rs.
+fn compress_transformed(
+    binary_data: &[u8],
+    checksum: Option<u32>,
+    width: Option<Width>,
+    options: &Options,
+) -> Result<Compressed, Error> {
+    let tile_len = tile_len(width, width::MAX_TILE_PIXELS, options);
+    let chunks: Vec<&[u8]> = binary_data.chunks(tile_len).collect();
+    let tiles = tile_count(binary_data.len(), tile_len)?;
+
+    let mut hasher = crc32fast::Hasher::new();
+    let (tiles, settings) = chunks
+        .iter()
+        .enumerate()
+        .map(|(tile, chunk)| {
+            hasher.update(chunk);
+            let header = Header {
+                layout: options.layout,
+                length: chunk.len() as u32,
+                tile: tile as u16,
+                tiles,
+                transforms: options.transforms.clone(),
+                checksum: checksum.unwrap_or_else(|| hasher.clone().finalize()),
+            };
+            compress_tile(chunk, &header, width, options)
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    Ok(Compressed { tiles, settings })
+}
+
+// Rejects options that can't produce an image for this much data
+fn check(len: usize, options: &Options) -> Result<(), Error> {
+    if len == 0 {
+        return Err(Error::EmptyInput);
+    }
+    if options.transforms.len() > MAX_TRANSFORMS {
+        return Err(Error::TooManyTransforms(options.transforms.len()));
+    }
+    // Widths from the command line are already checked, but library users can pass anything
+    if let Some(Width::Fixed(width)) = options.width {
+        if !(1..=width::MAX_DIMENSION).contains(&width) {
+            let pixels = HEADER_LEN + options.layout.pixels(len);
+            return Err(Error::DimensionOverflow {
+                width,
+                height: (pixels as u32).div_ceil(width.max(1)),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn compress(binary_data: &[u8], options: &Options) -> Result<Compressed, Error> {
+    check(binary_data.len(), options)?;
+    if options.transforms.is_empty() {
+        return compress_transformed(binary_data, None, options.width, options);
+    }
+    let checksum = Some(crc32fast::hash(binary_data));
+    if !options
+        .transforms
+        .iter()
+        .any(|transform| transform.is_aligned())
+    {
+        let transformed = transform::apply(&options.transforms, binary_data, Geometry::default())?;
+        return compress_transformed(&transformed, checksum, options.width, options);
+    }
+
+    // The row length of the aligned transforms has to match the image width
+    let stride = options.layout.stride();
+    let line_align = options.transforms.contains(&Transform::LineAlign);
+    let pixels = (HEADER_LEN + options.layout.pixels(binary_data.len())) as u32;
+    let widths = match options.width {
+        None if line_align => vec![width::default_line_width(binary_data, stride)],
+        None => vec![width::default_width(pixels)],
+        Some(Width::Fixed(width)) => vec![width],
+        Some(Width::Auto) if line_align => width::line_width_candidates(binary_data, stride),
+        Some(Width::Auto) => width::candidates(binary_data, pixels, stride),
+    };
+    let mut best: Option<Compressed> = None;
+    for width in widths {
+        let geometry = Geometry {
+            row_len: width as usize * stride,
+            offset: HEADER_LEN * stride,
+        };
+        let transformed = transform::apply(&options.transforms, binary_data, geometry)?;
+        let compressed =
+            compress_transformed(&transformed, checksum, Some(Width::Fixed(width)), options)?;
+        if best
+            .as_ref()
+            .is_none_or(|best| compressed.len() < best.len())
+        {
+            best = Some(compressed);
+        }
+    }
+    Ok(best.unwrap())
+}
+
+// Like `compress` without transforms, but reads the input one tile at a time and writes each image
+// as soon as it is encoded, so that memory use depends on the tile size rather than on the input
+// size. Tiles are smaller than in `compress` for the same reason, see STREAM_TILE_PIXELS, so large
+// inputs are split into more of them. Every header holds the tile count, so the length of the input is found by seeking to its
+// end first, while the checksum only covers the input up to the end of each tile and is computed
+// as the tiles are read. Returns the settings of each tile.
+pub fn compress_stream(
+    input: &mut (impl Read + Seek),
+    output: &mut impl Write,
+    options: &Options,
+) -> Result<Vec<Settings>, Error> {
+    // Transforms work on the whole input
+    if !options.transforms.is_empty() {
+        return Err(Error::StreamedTransforms);
+    }
+    let input_error = |error| Error::Io("input".into(), error);
+    let start = input.stream_position().map_err(input_error)?;
+    let end = input.seek(SeekFrom::End(0)).map_err(input_error)?;
+    let len = end.saturating_sub(start) as usize;
+    check(len, options)?;
+
+    input.seek(SeekFrom::Start(start)).map_err(input_error)?;
+    let tile_len = tile_len(options.width, width::STREAM_TILE_PIXELS, options);
+    let tiles = tile_count(len, tile_len)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut chunk = Vec::with_capacity(tile_len.min(len));
+    let mut settings = Vec::with_capacity(tiles as usize);
+    for tile in 0..tiles {
+        chunk.resize(tile_len.min(len - tile as usize * tile_len), 0);
+        input.read_exact(&mut chunk).map_err(input_error)?;
+        hasher.update(&chunk);
+        let header = Header {
+            layout: options.layout,
+            length: chunk.len() as u32,
+            tile,
+            tiles,
+            transforms: Vec::new(),
+            checksum: hasher.clone().finalize(),
+        };
+        let (webp, tile_settings) = compress_tile(&chunk, &header, options.width, options)?;
+        output
+            .write_all(&webp)
+            .map_err(|error| Error::Io("output".into(), error))?;
+        settings.push(tile_settings);
+    }
+    // The tile count would be wrong if the input grew in between, a shorter input already failed
+    if input.read(&mut [0]).map_err(input_error)? != 0 {
+        return Err(input_error(std::io::Error::other(
+            "the input changed while it was being compressed",
+        )));
+    }
+    Ok(settings)
+}
+
+fn decompress_tile(rgba: &[u8]) -> Result<(Header, Vec<u8>), Error> {
+    let pixels: Vec<Pixel> = rgba
+        .chunks(4)
+        .map(|pixel| pixel.try_into().unwrap())
+        .collect();
+
+    let header_bytes = Layout::Grayscale
+        .unpack(&pixels, HEADER_LEN)
+        .ok_or(header::HeaderError::Truncated)?;
+    let header = Header::parse(&header_bytes)?;
+    let data = header
+        .layout
+        .unpack(&pixels[HEADER_LEN..], header.length as usize)
+        .ok_or(Error::Truncated)?;
+    Ok((header, data))
+}
+
+pub fn decompress(images: &[u8]) -> Result<Vec<u8>, Error> {
+    if images.is_empty() {
+        return Err(Error::UnknownFormat);
+    }
+    let mut decoded: Vec<(Header, Vec<u8>)> = Vec::new();
+    let mut rest = images;
+    while !rest.is_empty() {
+        let backend = backend::detect(rest).ok_or(Error::UnknownFormat)?;
+        let (rgba, len) = backend.decode(rest)?;
+        decoded.push(decompress_tile(&rgba)?);
+        rest = &rest[len..];
+    }
+
+    // Tiles are written in order, but their headers are the source of truth
+    decoded.sort_by_key(|(header, _)| header.tile);
+    for (i, (header, _)) in decoded.iter().enumerate() {
+        if header.tile as usize != i || header.tiles as usize != decoded.len() {
+            return Err(Error::MissingTile {
+                tile: i as u16,
+                tiles: header.tiles.max(decoded.len() as u16),
+            });
+        }
+    }
+    let transforms = decoded[0].0.transforms.clone();
+    if !decoded
+        .iter()
+        .all(|(header, _)| header.transforms == transforms)
+    {
+        return Err(Error::InconsistentTiles);
+    }
+    // The last tile holds the checksum of the whole input
+    let checksum = decoded.last().unwrap().0.checksum;
+    let transformed = decoded.into_iter().flat_map(|(_, data)| data).collect();
+    let binary_data =
+        transform::invert(&transforms, transformed).ok_or(Error::MalformedTransform)?;
+    if crc32fast::hash(&binary_data) != checksum {
+        return Err(Error::ChecksumMismatch);
+    }
+    Ok(binary_data)
+}
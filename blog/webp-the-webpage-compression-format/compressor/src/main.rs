@@ -1,26 +1,263 @@
-use std::io::{Read, Write};
+use clap::{Parser, Subcommand};
+use compressor::bench::{self, Format};
+use compressor::encoding::Encoding;
+use compressor::width::Width;
+use compressor::{
+    compress, compress_stream, decompress, embed, site, watch, Error, Options, Settings,
+};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(about = "Compresses data into a lossless WebP image and back")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    compress: Options,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compress stdin into a WebP image on stdout (the default). If stdin is a file and no
+    /// transforms are used, it is read one tile at a time, so memory use doesn't grow with its size.
+    Compress(Options),
+    /// Decode a WebP image produced by `compress` from stdin and write the original data to stdout
+    Decompress,
+    /// Turn an HTML page into a self-decompressing one, keeping the original as a no-JS fallback
+    Embed {
+        /// The page to compress; everything above `<cut></cut>` is also kept uncompressed
+        input: PathBuf,
+        /// Where to write the self-decompressing page [default: overwrite the input]
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Where to write the original page [default: nojs.html next to the output]
+        #[arg(long)]
+        nojs: Option<PathBuf>,
+        /// Decode each byte by a majority vote over R, G and B, so that noise added to canvas
+        /// reads in a single channel is repaired (grayscale layout only)
+        #[arg(long)]
+        repair: bool,
+        /// Split the page into up to this many images: the first one with the top of the page, and
+        /// the rest inserted into it in order, each as soon as it is decoded
+        #[arg(long, default_value_t = 1)]
+        chunks: usize,
+        /// How the images are stored in the page
+        #[arg(long, value_enum, default_value_t = Encoding::Base64)]
+        encoding: Encoding,
+        #[command(flatten)]
+        compress: Options,
+    },
+    /// Compare the compressor, with every image backend, to gzip, zopfli, brotli and bzip2 on every
+    /// file in a directory
+    Bench {
+        /// The directory with the files to compress
+        #[arg(default_value = "corpus")]
+        corpus: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+        #[command(flatten)]
+        compress: Options,
+    },
+    /// Compress every index.html in a directory against a dictionary of shared markup and report
+    /// the total savings over compressing each page alone
+    Site {
+        /// The root of the site
+        root: PathBuf,
+        /// Where to write the dictionary image, relative to the root
+        #[arg(long, default_value = "dictionary.webp")]
+        dictionary: PathBuf,
+        /// How many pages a phrase has to occur in to be put into the dictionary
+        #[arg(long, default_value_t = 2)]
+        min_pages: usize,
+        /// Also write the dictionary and replace every page with a self-decompressing one, keeping
+        /// the original as nojs.html next to it
+        #[arg(long)]
+        write: bool,
+        /// Decode each byte by a majority vote over R, G and B, as in `embed`
+        #[arg(long)]
+        repair: bool,
+        #[command(flatten)]
+        compress: Options,
+    },
+    /// Watch a directory for index.html files regenerated by the blog build and replace each one
+    /// whose contents changed with a self-decompressing page, keeping the original as nojs.html
+    Watch {
+        /// The root of the site
+        root: PathBuf,
+        /// How often to look for changes, in milliseconds
+        #[arg(long, default_value_t = 500)]
+        interval: u64,
+        /// Decode each byte by a majority vote over R, G and B, as in `embed`
+        #[arg(long)]
+        repair: bool,
+        /// Split each page into up to this many images, as in `embed`
+        #[arg(long, default_value_t = 1)]
+        chunks: usize,
+        /// How the images are stored in the pages
+        #[arg(long, value_enum, default_value_t = Encoding::Base64)]
+        encoding: Encoding,
+        #[command(flatten)]
+        compress: Options,
+    },
+}
+
+fn read_stdin() -> Result<Vec<u8>, Error> {
+    let mut input = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut input)
+        .map_err(|error| Error::Io("stdin".into(), error))?;
+    Ok(input)
+}
+
+fn write_stdout(output: &[u8]) -> Result<(), Error> {
+    std::io::stdout()
+        .write_all(output)
+        .map_err(|error| Error::Io("stdout".into(), error))
+}
+
+// Stdin as a file that can be read twice, if it was redirected from one
+#[cfg(unix)]
+fn seekable_stdin() -> Option<File> {
+    use std::os::fd::AsFd;
+    let mut file = File::from(std::io::stdin().as_fd().try_clone_to_owned().ok()?);
+    // Fails for pipes and terminals
+    file.stream_position().ok()?;
+    Some(file)
+}
+
+#[cfg(not(unix))]
+fn seekable_stdin() -> Option<File> {
+    None
+}
+
+// Prints what the library picked for each tile, if there was anything to pick from
+fn report(settings: &[Settings], options: &Options) {
+    if settings.len() > 1 {
+        eprintln!("splitting into {} tiles", settings.len());
+    }
+    if options.width == Some(Width::Auto) || options.max_effort {
+        for Settings { width, config } in settings {
+            eprintln!(
+                "width {width}, method {}, quality {}",
+                config.method, config.quality
+            );
+        }
+    }
+}
+
+fn compress_stdin(options: &Options) -> Result<(), Error> {
+    match seekable_stdin() {
+        Some(mut file) if options.transforms.is_empty() => {
+            let mut stdout = std::io::stdout().lock();
+            let settings = compress_stream(&mut file, &mut stdout, options)?;
+            report(&settings, options);
+            stdout
+                .flush()
+                .map_err(|error| Error::Io("stdout".into(), error))
+        }
+        _ => {
+            let compressed = compress(&read_stdin()?, options)?;
+            report(&compressed.settings, options);
+            write_stdout(&compressed.to_bytes())
+        }
+    }
+}
+
+fn embed_file(
+    input: &Path,
+    output: Option<&Path>,
+    nojs: Option<&Path>,
+    repair: bool,
+    chunks: usize,
+    encoding: Encoding,
+    options: &Options,
+) -> Result<(), Error> {
+    let embedded = embed::embed_file(input, output, nojs, repair, chunks, encoding, options)?;
+    for compressed in &embedded.compressed {
+        report(&compressed.settings, options);
+    }
+    if encoding == Encoding::Auto {
+        for (encoding, transfer) in &embedded.transfers {
+            eprintln!("{encoding}: {transfer} bytes transferred");
+        }
+    }
+    let sizes: Vec<String> = embedded
+        .compressed
+        .iter()
+        .map(|chunk| chunk.len().to_string())
+        .collect();
+    eprintln!(
+        "{}: {} -> {} bytes, images of {} bytes, {} bytes transferred with {}",
+        output.unwrap_or(input).display(),
+        embedded.original_len,
+        embedded.page.len(),
+        sizes.join(" + "),
+        embedded.transfer,
+        embedded.encoding,
+    );
+    Ok(())
+}
+
+fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Compress(options) => compress_stdin(&options),
+        Command::Decompress => write_stdout(&decompress(&read_stdin()?)?),
+        Command::Embed {
+            input,
+            output,
+            nojs,
+            repair,
+            chunks,
+            encoding,
+            compress,
+        } => embed_file(
+            &input,
+            output.as_deref(),
+            nojs.as_deref(),
+            repair,
+            chunks,
+            encoding,
+            &compress,
+        ),
+        Command::Bench {
+            corpus,
+            format,
+            compress,
+        } => bench::run(&corpus, format, &compress),
+        Command::Site {
+            root,
+            dictionary,
+            min_pages,
+            write,
+            repair,
+            compress,
+        } => site::run(&root, &dictionary, min_pages, write, repair, &compress),
+        Command::Watch {
+            root,
+            interval,
+            repair,
+            chunks,
+            encoding,
+            compress,
+        } => watch::run(
+            &root,
+            Duration::from_millis(interval),
+            repair,
+            chunks,
+            encoding,
+            &compress,
+        ),
+    }
+}
 
 fn main() {
-    let mut binary_data = Vec::new();
-    std::io::stdin().read_to_end(&mut binary_data).expect("failed to read stdin");
-
-    // Umm... Nx2048?
-    let width = (binary_data.len() as u32).div_ceil(2048);
-    let height = (binary_data.len() as u32).div_ceil(width);
-    eprintln!("{width}x{height}");
-
-    // Convert to grayscale RGB, padding the image to width * height
-    let mut image_data: Vec<u8> = binary_data.iter().flat_map(|&b| [b, b, b]).collect();
-    image_data.resize((width * height * 3) as usize, 0);
-
-    // Lossless, quality 100, method 5
-    let mut config = webp::WebPConfig::new().unwrap();
-    config.lossless = 1;
-    config.quality = 100.0;
-    config.method = 5;
-    let compressed = webp::Encoder::from_rgb(&image_data, width, height)
-        .encode_advanced(&config)
-        .expect("encoding failed");
-
-    std::io::stdout().write_all(&compressed).expect("failed to write to stdout");
+    let cli = Cli::parse();
+    if let Err(error) = run(cli.command.unwrap_or(Command::Compress(cli.compress))) {
+        eprintln!("error: {error}");
+        std::process::exit(1);
+    }
 }
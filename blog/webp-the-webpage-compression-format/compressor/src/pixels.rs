@@ -0,0 +1,40 @@
+// The pixels of one tile as the encoder sees them: the header, one byte per pixel, then the data in
+// its layout, then opaque black up to the end of the last row, so that the alpha channel stays
+// unused unless the layout needs it. Pixels are computed when a backend asks for them instead of
+// being collected first, so a tile is only ever stored as pixels by the encoder itself.
+
+use crate::header::{Header, HEADER_LEN};
+use crate::layout::{Layout, Pixel};
+
+const PADDING: Pixel = [0, 0, 0, 255];
+
+pub struct Pixels<'a> {
+    header: [u8; HEADER_LEN],
+    layout: Layout,
+    data: &'a [u8],
+}
+
+impl<'a> Pixels<'a> {
+    pub fn new(header: &Header, data: &'a [u8]) -> Self {
+        Self {
+            header: header.to_bytes(),
+            layout: header.layout,
+            data,
+        }
+    }
+
+    // How many pixels hold the header and the data, not counting the padding
+    pub fn count(&self) -> usize {
+        HEADER_LEN + self.layout.pixels(self.data.len())
+    }
+
+    pub fn get(&self, i: usize) -> Pixel {
+        if i < HEADER_LEN {
+            Layout::Grayscale.pixel(&self.header, i)
+        } else if i < self.count() {
+            self.layout.pixel(self.data, i - HEADER_LEN)
+        } else {
+            PADDING
+        }
+    }
+}
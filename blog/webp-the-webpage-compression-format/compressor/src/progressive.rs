@@ -0,0 +1,158 @@
+// Splits a page into chunks that are compressed into separate images, so that the top of the page
+// appears as soon as the first image is decoded instead of after the whole page is.
+//
+// The first chunk is the skeleton: the page without the later children of the element that holds
+// most of the content below the cut, with a marker in their place. Every other chunk is a run of
+// complete sibling elements, which the bootstrap inserts before the marker in document order. The
+// browser parses each chunk as a fragment in the context of that element, so splitting only
+// between its children keeps the resulting DOM the same as parsing the page at once.
+//
+// Chunks grow geometrically, so that the first ones decode quickly and the later ones don't lose
+// too much compression to being separate.
+
+use crate::embed::CUT_MARKER;
+use crate::html::{Token, Tokenizer, Tree};
+use std::collections::HashMap;
+
+pub const MARKER_ID: &str = "wpc-rest";
+// A template can be put anywhere in the body and doesn't close an open `<p>` or `<li>`
+pub const MARKER: &str = "<template id=wpc-rest></template>";
+
+// The elements whose children the page can be split between. Tables, lists of definitions, inline
+// and foreign elements are left out: their contents are not parsed the same way in a fragment.
+const CONTAINERS: [&str; 15] = [
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "details",
+    "div",
+    "figure",
+    "footer",
+    "header",
+    "html",
+    "main",
+    "nav",
+    "ol",
+    "section",
+    "ul",
+];
+
+// A position before a start tag at which the element on top of the stack, once the tag has closed
+// what it implies, is the container
+struct SplitPoint<'a> {
+    position: usize,
+    // The elements the start tag closes implicitly, from the top of the stack
+    implied_closes: Vec<&'a str>,
+}
+
+// Returns up to `count` chunks, the skeleton first. If the page can't be split, it is returned as
+// a single chunk.
+pub fn split(html: &str, count: usize) -> Vec<String> {
+    let start = html
+        .find(CUT_MARKER)
+        .map_or(0, |cut| cut + CUT_MARKER.len());
+
+    // Split points grouped by the container, which is identified by the ids of its ancestors
+    let mut containers: HashMap<Vec<usize>, Vec<SplitPoint>> = HashMap::new();
+    let mut tree = Tree::default();
+    for (range, token) in Tokenizer::new(html) {
+        if let Token::Start { name, .. } = token {
+            let closes = tree.implied_closes(name);
+            let container = &tree.stack[..tree.stack.len() - closes];
+            if range.start >= start
+                && !container.is_empty()
+                && container
+                    .iter()
+                    .all(|element| CONTAINERS.contains(&element.name))
+            {
+                containers
+                    .entry(container.iter().map(|element| element.id).collect())
+                    .or_default()
+                    .push(SplitPoint {
+                        position: range.start,
+                        implied_closes: container_closes(&tree, closes),
+                    });
+            }
+        }
+        tree.process(token);
+    }
+
+    // The container with the most content between its split points, not counting the largest gap
+    // between them, which can't be split. Otherwise the body would win on every page, being split
+    // only around the element with the content.
+    let Some((ids, points)) = containers.into_iter().max_by_key(|(ids, points)| {
+        let gaps = points
+            .windows(2)
+            .map(|pair| pair[1].position - pair[0].position);
+        (
+            gaps.clone().sum::<usize>() - gaps.max().unwrap_or(0),
+            ids.clone(),
+        )
+    }) else {
+        return vec![html.to_string()];
+    };
+    let end = container_end(html, &ids, points.last().unwrap().position);
+
+    // Chunk i after the skeleton is 2^i times as large as the first one, roughly
+    let len = end - points[0].position;
+    let parts = count.saturating_sub(1).min(points.len());
+    let mut chosen: Vec<&SplitPoint> = Vec::new();
+    for i in 0..parts {
+        let fraction = (2f64.powi(i as i32) - 1.0) / (2f64.powi(parts as i32) - 1.0);
+        let target = points[0].position + (len as f64 * fraction) as usize;
+        let next = points
+            .iter()
+            .filter(|point| {
+                chosen
+                    .last()
+                    .is_none_or(|last| point.position > last.position)
+            })
+            .min_by_key(|point| point.position.abs_diff(target));
+        match next {
+            Some(point) => chosen.push(point),
+            None => break,
+        }
+    }
+    let Some(first) = chosen.first() else {
+        return vec![html.to_string()];
+    };
+
+    let mut skeleton = html[..first.position].to_string();
+    for name in &first.implied_closes {
+        skeleton.push_str(&format!("</{name}>"));
+    }
+    skeleton.push_str(MARKER);
+    skeleton.push_str(&html[end..]);
+    let mut chunks = vec![skeleton];
+    for (i, point) in chosen.iter().enumerate() {
+        let next = chosen.get(i + 1).map_or(end, |next| next.position);
+        chunks.push(html[point.position..next].to_string());
+    }
+    chunks
+}
+
+fn container_closes<'a>(tree: &Tree<'a>, closes: usize) -> Vec<&'a str> {
+    tree.stack[tree.stack.len() - closes..]
+        .iter()
+        .rev()
+        .map(|element| element.name)
+        .collect()
+}
+
+// The position of the token that closes the container, or the end of the page
+fn container_end(html: &str, ids: &[usize], after: usize) -> usize {
+    let mut tree = Tree::default();
+    for (range, token) in Tokenizer::new(html) {
+        tree.process(token);
+        let open = tree.stack.len() >= ids.len()
+            && tree.stack[..ids.len()]
+                .iter()
+                .zip(ids)
+                .all(|(element, &id)| element.id == id);
+        if range.start > after && !open {
+            return range.start;
+        }
+    }
+    html.len()
+}
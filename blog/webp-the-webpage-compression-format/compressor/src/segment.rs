@@ -0,0 +1,101 @@
+// Splits a page into runs of the same kind of content: markup, scripts, styles and inline SVG or
+// MathML. Each kind has its own byte statistics, and VP8L can switch prefix codes every block of
+// pixels, so the segment-align transform starts each segment on a new row of blocks. Blocks then
+// hold one kind of content each, and the encoder can give every kind its own group of codes.
+
+use crate::html::{Token, Tokenizer};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Markup,
+    Script,
+    Style,
+    Svg,
+    Math,
+}
+
+// Returns ranges covering the data in order. Segments shorter than `min_len` don't pay for the
+// padding before them and are merged into the previous one. Data that isn't UTF-8, e.g. because
+// another transform was applied first, is a single segment.
+pub fn segments(data: &[u8], min_len: usize) -> Vec<(Kind, Range<usize>)> {
+    let Ok(html) = std::str::from_utf8(data) else {
+        return vec![(Kind::Markup, 0..data.len())];
+    };
+
+    // Where each kind of content starts
+    let mut starts = vec![(Kind::Markup, 0)];
+    let mut foreign: Option<(Kind, usize)> = None;
+    let mut raw_text = None;
+    for (range, token) in Tokenizer::new(html) {
+        match (token, &mut foreign) {
+            (Token::Start { name, self_closing }, None) => {
+                let kind = match name {
+                    "svg" => Kind::Svg,
+                    "math" => Kind::Math,
+                    "script" => {
+                        raw_text = Some(Kind::Script);
+                        continue;
+                    }
+                    "style" => {
+                        raw_text = Some(Kind::Style);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if !self_closing {
+                    starts.push((kind, range.start));
+                    foreign = Some((kind, 1));
+                }
+            }
+            (Token::Start { name, self_closing }, Some((kind, depth)))
+                if name == tag(*kind) && !self_closing =>
+            {
+                *depth += 1;
+            }
+            (Token::End { name }, Some((kind, depth))) if name == tag(*kind) => {
+                *depth -= 1;
+                if *depth == 0 {
+                    starts.push((Kind::Markup, range.end));
+                    foreign = None;
+                }
+            }
+            (Token::Text, None) => {
+                if let Some(kind) = raw_text.take() {
+                    starts.push((kind, range.start));
+                    starts.push((Kind::Markup, range.end));
+                }
+            }
+            _ => {}
+        }
+        raw_text = None;
+    }
+
+    let mut segments: Vec<(Kind, Range<usize>)> = Vec::new();
+    for (i, &(kind, start)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(data.len(), |&(_, end)| end);
+        if start == end {
+            continue;
+        }
+        match segments.last_mut() {
+            Some((last_kind, last)) if *last_kind == kind || end - start < min_len => {
+                last.end = end;
+            }
+            _ => segments.push((kind, start..end)),
+        }
+    }
+    // A short segment at the start can only be merged into the next one
+    if segments.len() > 1 && segments[0].1.len() < min_len {
+        let (_, first) = segments.remove(0);
+        segments[0].1.start = first.start;
+    }
+    segments
+}
+
+fn tag(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Svg => "svg",
+        Kind::Math => "math",
+        _ => unreachable!("only foreign content is nested"),
+    }
+}
@@ -0,0 +1,176 @@
+// Decodes embedded pages the way the bootstrap in embed.rs does in the browser, step by step, so
+// that tests can check what the browser ends up with rather than what `decompress` reads:
+//
+// - createImageBitmap decodes the image and premultiplies alpha unless told not to. The images
+//   carry no color profile, so color space conversion does nothing; images with one are rejected.
+// - texImage2D uploads the first row of the bitmap to the bottom row of the texture, as
+//   UNPACK_FLIP_Y_WEBGL is off, and undoes premultiplication, as UNPACK_PREMULTIPLY_ALPHA_WEBGL is
+//   off too. Both conversions round, so they are lossy.
+// - readPixels returns rows from the bottom of the framebuffer up, so the two flips cancel out.
+// - Data bytes are read at the offsets the script computes, see `ByteOffsets`, not with
+//   `Layout::unpack`, and the script trusts the header only for the length and the checksum.
+//
+// The script itself is run by tests/simulate.rs under Node.js, with WebGL replaced by
+// `read_pixels`, when Node.js is installed.
+
+use crate::backend;
+use crate::dictionary::Dictionary;
+use crate::embed::{premultiplies_alpha, ByteOffsets, Options};
+use crate::header::{CHECKSUM_OFFSET, LENGTH_OFFSET};
+use crate::progressive::MARKER;
+use crate::transform;
+use crate::{Compressed, Error};
+
+// VP8X flag for an ICC profile
+const ICC_FLAG: u8 = 0x20;
+
+fn has_color_profile(image: &[u8]) -> bool {
+    image.get(12..16) == Some(b"VP8X") && image.get(20).is_some_and(|flags| flags & ICC_FLAG != 0)
+}
+
+fn premultiply(channel: u8, alpha: u8) -> u8 {
+    ((channel as u32 * alpha as u32 + 127) / 255) as u8
+}
+
+fn unpremultiply(channel: u8, alpha: u8) -> u8 {
+    match alpha {
+        0 => 0,
+        _ => ((channel as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8,
+    }
+}
+
+// What `p` holds after readPixels, for a bitmap created with or without premultiplied alpha
+pub fn read_pixels(image: &[u8], premultiplied: bool) -> Result<Vec<u8>, Error> {
+    let backend = backend::detect(image).ok_or(Error::UnknownFormat)?;
+    if backend.mime_type().is_none() || has_color_profile(image) {
+        return Err(Error::Decode);
+    }
+    let (width, height) = backend.dimensions(image).ok_or(Error::Decode)?;
+    let (mut bitmap, _) = backend.decode(image)?;
+    if premultiplied {
+        for pixel in bitmap.chunks_mut(4) {
+            for channel in 0..3 {
+                pixel[channel] = premultiply(pixel[channel], pixel[3]);
+            }
+        }
+    }
+
+    // The texture as it would be drawn, top row first. GL row 0 is the bottom one, and without
+    // flipping, the first row of the bitmap, i.e. the top one, goes there.
+    let stride = width as usize * 4;
+    let height = height as usize;
+    let drawn_row = |gl_row: usize| (height - 1 - gl_row) * stride;
+    let mut texture = vec![0; bitmap.len()];
+    for (gl_row, pixels) in bitmap.chunks(stride).enumerate() {
+        texture[drawn_row(gl_row)..][..stride].copy_from_slice(pixels);
+    }
+    if premultiplied {
+        for pixel in texture.chunks_mut(4) {
+            for channel in 0..3 {
+                pixel[channel] = unpremultiply(pixel[channel], pixel[3]);
+            }
+        }
+    }
+
+    // readPixels(0, 0, w, h) starts at GL row 0, the bottom one
+    let mut p = Vec::with_capacity(texture.len());
+    for gl_row in 0..height {
+        p.extend(&texture[drawn_row(gl_row)..][..stride]);
+    }
+    Ok(p)
+}
+
+// `f` in the bootstrap: the data bytes of one image and the checksum from its header
+fn decode_image(image: &[u8], options: &Options) -> Result<(Vec<u8>, u32), Error> {
+    let p = read_pixels(image, premultiplies_alpha(options.layout))?;
+    let read = |o: usize| -> Result<u8, Error> {
+        let at = |o: usize| p.get(o).copied().ok_or(Error::Truncated);
+        if !options.repair {
+            return at(o);
+        }
+        // Majority vote, see `bootstrap`
+        let (r, g, b) = (at(o)?, at(o + 1)?, at(o + 2)?);
+        Ok(if r == g || r == b { r } else { g })
+    };
+    let header_u32 = |start: usize| -> Result<u32, Error> {
+        let mut bytes = [0; 4];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = read((start + i) * 4)?;
+        }
+        Ok(u32::from_le_bytes(bytes))
+    };
+    let length = header_u32(LENGTH_OFFSET)? as usize;
+    let checksum = header_u32(CHECKSUM_OFFSET)?;
+    let offsets = ByteOffsets::of(options.layout);
+    let data = (0..length)
+        .map(|i| read(offsets.offset(i, length)))
+        .collect::<Result<_, _>>()?;
+    Ok((data, checksum))
+}
+
+// `r` in the bootstrap: the bytes of one chunk, checked against the checksum in the header of its
+// last tile, before they are decoded as text
+pub fn decode_chunk(
+    tiles: &[Vec<u8>],
+    dictionary: Option<&Dictionary>,
+    options: &Options,
+) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    let mut checksum = None;
+    for tile in tiles {
+        let (tile_data, tile_checksum) = decode_image(tile, options)?;
+        data.extend(tile_data);
+        checksum = Some(tile_checksum);
+    }
+    let data = transform::invert(options.transforms, data).ok_or(Error::MalformedTransform)?;
+    if Some(crc32fast::hash(&data)) != checksum {
+        return Err(Error::ChecksumMismatch);
+    }
+    match dictionary {
+        Some(dictionary) => dictionary.expand(&data).ok_or(Error::Decode),
+        None => Ok(data),
+    }
+}
+
+// The dictionary as the bootstrap reads it: one image, checked, then split into phrases
+pub fn decode_dictionary(image: &[u8], options: &Options) -> Result<Dictionary, Error> {
+    let options = Options {
+        transforms: &[],
+        ..*options
+    };
+    let (data, checksum) = decode_image(image, &options)?;
+    if crc32fast::hash(&data) != checksum {
+        return Err(Error::ChecksumMismatch);
+    }
+    Ok(Dictionary::from_bytes(&data))
+}
+
+// The document after the bootstrap has run: the first chunk replaces it, and the others are
+// inserted before the marker, which is removed at the end. Text is decoded like TextDecoder does,
+// replacing invalid UTF-8.
+pub fn document(
+    chunks: &[Compressed],
+    dictionary: Option<&[u8]>,
+    options: &Options,
+) -> Result<String, Error> {
+    assert_eq!(
+        dictionary.is_some(),
+        options.dictionary_url.is_some(),
+        "the dictionary has to match the options"
+    );
+    let dictionary = dictionary
+        .map(|image| decode_dictionary(image, options))
+        .transpose()?;
+    let mut document = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let data = decode_chunk(&chunk.tiles, dictionary.as_ref(), options)?;
+        let text = String::from_utf8_lossy(&data);
+        if i == 0 {
+            document = text.into_owned();
+        } else {
+            let at = document.find(MARKER).ok_or(Error::Decode)?;
+            document.insert_str(at, &text);
+        }
+    }
+    Ok(document.replacen(MARKER, "", 1))
+}
@@ -0,0 +1,198 @@
+// Site mode: compresses every page of a site against a shared dictionary, see dictionary.rs, and
+// reports how much smaller the whole site gets than with each page compressed on its own. Sizes are
+// transfer sizes, i.e. gzipped, as pages are served. The dictionary image is counted once, as the
+// browser caches it after the first page.
+
+use crate::dictionary::Dictionary;
+use crate::embed::{self, Options};
+use crate::encoding::Encoding;
+use crate::error::{self, read_dir, read_to_string};
+use crate::{compress, decompress, Error};
+use std::path::{Path, PathBuf};
+
+// Pages are the index.html files of the directory and its subdirectories, like the blog builds
+// them. Hidden directories and node_modules are skipped.
+pub fn find_pages(dir: &Path, pages: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for path in read_dir(dir)? {
+        let name = path.file_name().unwrap().to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "node_modules" {
+                find_pages(&path, pages)?;
+            }
+        } else if name == "index.html" {
+            pages.push(path);
+        }
+    }
+    Ok(())
+}
+
+// The dictionary is linked relative to the page, so that the site can be served from any prefix
+fn relative_url(root: &Path, page: &Path, target: &Path) -> String {
+    let depth = page
+        .parent()
+        .unwrap()
+        .strip_prefix(root)
+        .unwrap()
+        .components()
+        .count();
+    let target: Vec<String> = target
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    format!("{}{}", "../".repeat(depth), target.join("/"))
+}
+
+struct Page {
+    path: PathBuf,
+    html: String,
+    shared: String,
+    // Transfer sizes of the original, the page compressed alone and with the dictionary
+    raw_size: usize,
+    alone_size: usize,
+    shared_size: usize,
+}
+
+pub fn run(
+    root: &Path,
+    dictionary_path: &Path,
+    min_pages: usize,
+    write: bool,
+    repair: bool,
+    args: &crate::Options,
+) -> Result<(), Error> {
+    embed::check_repair(repair, args.layout)?;
+    embed::check_browser_support(args)?;
+    let mut paths = Vec::new();
+    find_pages(root, &mut paths)?;
+    let htmls: Vec<String> = paths
+        .iter()
+        .map(|path| read_to_string(path))
+        .collect::<Result<_, _>>()?;
+    // The dictionary would be learned from the bootstrap, and --write would overwrite the originals
+    if let Some((path, _)) = paths
+        .iter()
+        .zip(&htmls)
+        .find(|(_, html)| embed::is_embedded(html))
+    {
+        return Err(Error::AlreadyEmbedded(path.clone()));
+    }
+
+    let dictionary = Dictionary::learn(
+        &htmls.iter().map(|html| html.as_bytes()).collect::<Vec<_>>(),
+        min_pages,
+    );
+    if dictionary.is_empty() {
+        return Err(Error::EmptyDictionary);
+    }
+    // The bootstrap doesn't invert transforms for the dictionary
+    let dictionary_args = crate::Options {
+        transforms: Vec::new(),
+        ..args.clone()
+    };
+    let dictionary_webp = compress(&dictionary.to_bytes(), &dictionary_args)?;
+    // Pages are checked against the dictionary as the browser would see it
+    let decoded_dictionary = Dictionary::from_bytes(&decompress(&dictionary_webp.to_bytes())?);
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let pages: Vec<Page> = paths
+        .into_iter()
+        .zip(htmls)
+        .collect::<Vec<_>>()
+        .chunks(threads)
+        .flat_map(|batch| {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .iter()
+                    .map(|(path, html)| {
+                        let (dictionary, decoded_dictionary) = (&dictionary, &decoded_dictionary);
+                        scope.spawn(move || {
+                            let dictionary_url = relative_url(root, path, dictionary_path);
+                            let options = Options {
+                                layout: args.layout,
+                                transforms: &args.transforms,
+                                repair,
+                                nojs_url: "nojs.html",
+                                dictionary_url: None,
+                                encoding: Encoding::Base64,
+                                external_url: None,
+                            };
+                            let alone =
+                                embed::embed(html, &[compress(html.as_bytes(), args)?], &options)?;
+
+                            let substituted = dictionary.substitute(html.as_bytes());
+                            let compressed = compress(&substituted, args)?;
+                            if decoded_dictionary
+                                .expand(&decompress(&compressed.to_bytes())?)
+                                .as_deref()
+                                != Some(html.as_bytes())
+                            {
+                                return Err(Error::DictionaryRoundTrip(path.clone()));
+                            }
+                            let options = Options {
+                                dictionary_url: Some(&dictionary_url),
+                                ..options
+                            };
+                            let shared =
+                                embed::embed(html, std::slice::from_ref(&compressed), &options)?;
+                            Ok(Page {
+                                path: path.clone(),
+                                html: html.clone(),
+                                raw_size: embed::gzipped_len(html.as_bytes()),
+                                alone_size: embed::gzipped_len(alone.as_bytes()),
+                                shared_size: embed::gzipped_len(shared.as_bytes()),
+                                shared,
+                            })
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    println!(
+        "{:<60} {:>10} {:>10} {:>10}",
+        "page (gzipped bytes)", "raw", "alone", "shared"
+    );
+    for page in &pages {
+        let name = page.path.strip_prefix(root).unwrap().display().to_string();
+        println!(
+            "{name:<60} {:>10} {:>10} {:>10}",
+            page.raw_size, page.alone_size, page.shared_size
+        );
+    }
+    let raw: usize = pages.iter().map(|page| page.raw_size).sum();
+    let alone: usize = pages.iter().map(|page| page.alone_size).sum();
+    // The image is served as is, gzip wouldn't make it smaller
+    let shared: usize =
+        pages.iter().map(|page| page.shared_size).sum::<usize>() + dictionary_webp.len();
+    println!(
+        "{:<60} {:>10} {:>10} {:>10}",
+        format!(
+            "{} ({} phrases)",
+            dictionary_path.display(),
+            dictionary.len()
+        ),
+        "",
+        "",
+        dictionary_webp.len()
+    );
+    println!("{:<60} {raw:>10} {alone:>10} {shared:>10}", "total");
+    println!(
+        "the dictionary saves {} bytes ({:.2}%) over compressing each page alone",
+        alone as i64 - shared as i64,
+        (alone as f64 - shared as f64) / alone as f64 * 100.0
+    );
+
+    if write {
+        error::write(&root.join(dictionary_path), dictionary_webp.to_bytes())?;
+        for page in &pages {
+            error::write(&page.path.with_file_name("nojs.html"), &page.html)?;
+            error::write(&page.path, &page.shared)?;
+        }
+    }
+    Ok(())
+}
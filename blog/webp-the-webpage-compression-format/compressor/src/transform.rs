@@ -0,0 +1,324 @@
+// Reversible preprocessing applied to the whole input before it's split into tiles and packed into
+// pixels. VP8L's predictors are designed for images, so text benefits from being reshaped first.
+// Each transform stores whatever it needs for inversion at the start of its output.
+
+use crate::segment;
+use crate::Error;
+
+// The header has room for this many transforms
+pub const MAX_TRANSFORMS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Transform {
+    /// Burrows-Wheeler transform of the cyclic rotations, prefixed with the 32-bit primary index
+    Bwt = 1,
+    /// Move-to-front coding, which turns the runs produced by BWT into zeros
+    Mtf = 2,
+    /// Differences between consecutive bytes
+    Delta = 3,
+    /// Each line starts on a new row, so that vertical prediction finds repeated indentation.
+    /// Prefixed with the 16-bit row length, the 16-bit offset and the 32-bit original length.
+    LineAlign = 4,
+    /// Markup, scripts, styles and inline SVG each start on a new row of 16x16 blocks, so that the
+    /// encoder can use different prefix codes for them. Prefixed with the sizes of the segments.
+    SegmentAlign = 5,
+}
+
+// The height of the blocks that segments are aligned to. VP8L encoders usually pick blocks of 2^4
+// pixels or smaller for images of this size.
+pub const BLOCK_ROWS: usize = 16;
+
+// Where the transformed data is placed in the images, for the transforms that align it to rows
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Geometry {
+    // The length of an image row in bytes
+    pub row_len: usize,
+    // The number of bytes before the data in the image, i.e. the header
+    pub offset: usize,
+}
+
+impl Transform {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Self::Bwt),
+            2 => Some(Self::Mtf),
+            3 => Some(Self::Delta),
+            4 => Some(Self::LineAlign),
+            5 => Some(Self::SegmentAlign),
+            _ => None,
+        }
+    }
+
+    // Whether the transform needs to know the geometry of the image
+    pub fn is_aligned(self) -> bool {
+        matches!(self, Self::LineAlign | Self::SegmentAlign)
+    }
+
+    // `geometry` is only used by the aligned transforms, which fail if its rows don't fit in the
+    // 16-bit row length
+    pub fn forward(self, data: &[u8], geometry: Geometry) -> Result<Vec<u8>, Error> {
+        if self.is_aligned() && !(1..=u16::MAX as usize).contains(&geometry.row_len) {
+            return Err(Error::RowLength(geometry.row_len));
+        }
+        Ok(match self {
+            Self::Bwt => bwt(data),
+            Self::Mtf => mtf(data),
+            Self::Delta => delta(data),
+            Self::LineAlign => line_align(data, geometry),
+            Self::SegmentAlign => segment_align(data, geometry),
+        })
+    }
+
+    // Returns None if the data is malformed
+    pub fn inverse(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::Bwt => inverse_bwt(data),
+            Self::Mtf => Some(inverse_mtf(data)),
+            Self::Delta => Some(inverse_delta(data)),
+            Self::LineAlign => inverse_line_align(data),
+            Self::SegmentAlign => inverse_segment_align(data),
+        }
+    }
+}
+
+pub fn apply(transforms: &[Transform], data: &[u8], geometry: Geometry) -> Result<Vec<u8>, Error> {
+    let mut data = data.to_vec();
+    for transform in transforms {
+        data = transform.forward(&data, geometry)?;
+    }
+    Ok(data)
+}
+
+pub fn invert(transforms: &[Transform], data: Vec<u8>) -> Option<Vec<u8>> {
+    let mut data = data;
+    for transform in transforms.iter().rev() {
+        data = transform.inverse(&data)?;
+    }
+    Some(data)
+}
+
+// Sorts the cyclic rotations by prefix doubling: after each round, rotations are ranked by their
+// first 2k bytes
+fn sorted_rotations(data: &[u8]) -> Vec<u32> {
+    let n = data.len();
+    let mut order: Vec<u32> = (0..n as u32).collect();
+    let mut rank: Vec<u32> = data.iter().map(|&b| b as u32).collect();
+    let mut new_rank = vec![0; n];
+    let mut k = 1;
+    loop {
+        let key = |i: u32| (rank[i as usize], rank[(i as usize + k) % n]);
+        order.sort_unstable_by_key(|&i| key(i));
+        new_rank[order[0] as usize] = 0;
+        for pair in order.windows(2) {
+            new_rank[pair[1] as usize] =
+                new_rank[pair[0] as usize] + (key(pair[0]) != key(pair[1])) as u32;
+        }
+        std::mem::swap(&mut rank, &mut new_rank);
+        // Periodic inputs never get distinct ranks, but equal rotations are equal strings anyway
+        if rank[order[n - 1] as usize] as usize == n - 1 || k >= n {
+            return order;
+        }
+        k *= 2;
+    }
+}
+
+fn bwt(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        return vec![0; 4];
+    }
+    let n = data.len();
+    let order = sorted_rotations(data);
+    let primary_index = order.iter().position(|&i| i == 0).unwrap() as u32;
+    let mut output = primary_index.to_le_bytes().to_vec();
+    output.extend(order.iter().map(|&i| data[(i as usize + n - 1) % n]));
+    output
+}
+
+// Walks the LF mapping backwards from the row holding the original string
+fn inverse_bwt(data: &[u8]) -> Option<Vec<u8>> {
+    let (primary_index, last_column) = data.split_first_chunk::<4>()?;
+    let primary_index = u32::from_le_bytes(*primary_index) as usize;
+    let n = last_column.len();
+    if n == 0 {
+        return Some(Vec::new());
+    }
+    if primary_index >= n {
+        return None;
+    }
+
+    // Where each byte value starts in the first column
+    let mut starts = [0; 257];
+    for &b in last_column {
+        starts[b as usize + 1] += 1;
+    }
+    for i in 0..256 {
+        starts[i + 1] += starts[i];
+    }
+    let lf: Vec<usize> = last_column
+        .iter()
+        .map(|&b| {
+            starts[b as usize] += 1;
+            starts[b as usize] - 1
+        })
+        .collect();
+
+    let mut output = vec![0; n];
+    let mut row = primary_index;
+    for byte in output.iter_mut().rev() {
+        *byte = last_column[row];
+        row = lf[row];
+    }
+    Some(output)
+}
+
+fn mtf(data: &[u8]) -> Vec<u8> {
+    let mut recent: Vec<u8> = (0..=255).collect();
+    data.iter()
+        .map(|&b| {
+            let index = recent.iter().position(|&r| r == b).unwrap();
+            recent.remove(index);
+            recent.insert(0, b);
+            index as u8
+        })
+        .collect()
+}
+
+fn inverse_mtf(data: &[u8]) -> Vec<u8> {
+    let mut recent: Vec<u8> = (0..=255).collect();
+    data.iter()
+        .map(|&index| {
+            let b = recent.remove(index as usize);
+            recent.insert(0, b);
+            b
+        })
+        .collect()
+}
+
+fn delta(data: &[u8]) -> Vec<u8> {
+    let mut previous = 0;
+    data.iter()
+        .map(|&b| {
+            let difference = b.wrapping_sub(previous);
+            previous = b;
+            difference
+        })
+        .collect()
+}
+
+fn inverse_delta(data: &[u8]) -> Vec<u8> {
+    let mut previous = 0u8;
+    data.iter()
+        .map(|&difference| {
+            previous = previous.wrapping_add(difference);
+            previous
+        })
+        .collect()
+}
+
+// Rows are `row_len` bytes long and start where the rows of the image do, counting the bytes before
+// the data in the image. A row ends after a newline, or when it's full, in which case the line
+// continues on the next row. The rest of the row is zero, and so is the gap before the first row.
+fn line_align(data: &[u8], geometry: Geometry) -> Vec<u8> {
+    let row_len = geometry.row_len;
+    let offset = geometry.offset % row_len;
+
+    let mut output = Vec::with_capacity(data.len() + row_len);
+    output.extend((row_len as u16).to_le_bytes());
+    output.extend((offset as u16).to_le_bytes());
+    output.extend((data.len() as u32).to_le_bytes());
+    output.resize(
+        output.len() + block_padding(output.len(), row_len, offset),
+        0,
+    );
+    let mut position = 0;
+    while position < data.len() {
+        let row = &data[position..(position + row_len).min(data.len())];
+        let used = row
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(row.len(), |i| i + 1);
+        output.extend(&row[..used]);
+        output.resize(output.len() + row_len - used, 0);
+        position += used;
+    }
+    output
+}
+
+fn inverse_line_align(data: &[u8]) -> Option<Vec<u8>> {
+    let (row_len, data) = data.split_first_chunk::<2>()?;
+    let (offset, data) = data.split_first_chunk::<2>()?;
+    let (length, _) = data.split_first_chunk::<4>()?;
+    let row_len = u16::from_le_bytes(*row_len) as usize;
+    let offset = u16::from_le_bytes(*offset) as usize;
+    let length = u32::from_le_bytes(*length) as usize;
+    if row_len == 0 {
+        return None;
+    }
+    let rows = data.get(4 + block_padding(8, row_len, offset)..)?;
+
+    let mut output = Vec::with_capacity(length);
+    for row in rows.chunks(row_len) {
+        if output.len() == length {
+            break;
+        }
+        let used = row
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(row.len(), |i| i + 1)
+            .min(length - output.len());
+        output.extend(&row[..used]);
+    }
+    (output.len() == length).then_some(output)
+}
+
+// How many bytes of padding bring `position` to the start of a row of blocks
+fn block_padding(position: usize, block_len: usize, offset: usize) -> usize {
+    (block_len - (offset + position) % block_len) % block_len
+}
+
+// Each segment starts at the first row of blocks after the previous one ends, counting the bytes
+// before the data in the image. The gaps are zero. Prefixed with the 32-bit length of a row of
+// blocks, the offset, the number of segments and their lengths.
+fn segment_align(data: &[u8], geometry: Geometry) -> Vec<u8> {
+    let block_len = geometry.row_len * BLOCK_ROWS;
+    let offset = geometry.offset % block_len;
+    let segments = segment::segments(data, block_len);
+
+    let mut output = Vec::with_capacity(data.len() + segments.len() * block_len);
+    for n in [block_len, offset, segments.len()] {
+        output.extend((n as u32).to_le_bytes());
+    }
+    for (_, range) in &segments {
+        output.extend((range.len() as u32).to_le_bytes());
+    }
+    for (_, range) in segments {
+        output.resize(
+            output.len() + block_padding(output.len(), block_len, offset),
+            0,
+        );
+        output.extend(&data[range]);
+    }
+    output
+}
+
+fn inverse_segment_align(data: &[u8]) -> Option<Vec<u8>> {
+    let mut numbers = data
+        .chunks_exact(4)
+        .map(|n| u32::from_le_bytes(n.try_into().unwrap()) as usize);
+    let (block_len, offset, count) = (numbers.next()?, numbers.next()?, numbers.next()?);
+    if block_len == 0 || count > data.len() / 4 {
+        return None;
+    }
+    let lengths: Vec<usize> = numbers.take(count).collect();
+    if lengths.len() != count {
+        return None;
+    }
+    let mut position = 12 + 4 * count;
+    let mut output = Vec::new();
+    for length in lengths {
+        position += block_padding(position, block_len, offset);
+        output.extend(data.get(position..position.checked_add(length)?)?);
+        position += length;
+    }
+    Some(output)
+}
@@ -0,0 +1,432 @@
+// A lossless WebP (VP8L) encoder for data packed into pixels. libwebp is tuned for photos, while
+// text gains the most from backward references and from prefix codes that follow the kind of
+// content, so that's where this encoder spends its effort: an optimal LZ77 parse that accounts for
+// the 2D distance codes, prefix code groups clustered per block, and a search for the color cache
+// size. The only transform it uses is subtracting green, which makes grayscale free in red and blue;
+// predictors and color transforms don't help with text.
+//
+// See https://developers.google.com/speed/webp/docs/webp_lossless_bitstream_specification.
+
+mod backward_refs;
+mod cluster;
+mod huffman;
+
+use backward_refs::{DistanceCodes, MatchFinder, Token};
+use cluster::EntropyImage;
+use huffman::Code;
+use std::ops::Range;
+
+const SIGNATURE: u32 = 0x2f;
+const SUBTRACT_GREEN: u32 = 2;
+const NUM_LITERALS: usize = 256;
+const NUM_LENGTH_CODES: usize = 24;
+const NUM_DISTANCE_CODES: usize = 40;
+const MAX_CACHE_BITS: u32 = 10;
+const INITIAL_LENGTH_RARITY: usize = 64;
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.used;
+        self.used += bits;
+        while self.used >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.used -= 8;
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.bytes.len() as u64 * 8 + self.used as u64
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.used > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// A group has five prefix codes: green together with length prefixes and color cache keys, red,
+// blue, alpha and distance. Their histograms are stored one after another.
+#[derive(Clone, Copy)]
+struct Alphabets {
+    cache_bits: u32,
+}
+
+impl Alphabets {
+    fn ranges(self) -> [Range<usize>; 5] {
+        let cache_size = if self.cache_bits > 0 {
+            1 << self.cache_bits
+        } else {
+            0
+        };
+        let green = NUM_LITERALS + NUM_LENGTH_CODES + cache_size;
+        [
+            0..green,
+            green..green + 256,
+            green + 256..green + 512,
+            green + 512..green + 768,
+            green + 768..green + 768 + NUM_DISTANCE_CODES,
+        ]
+    }
+
+    fn len(self) -> usize {
+        self.ranges()[4].end
+    }
+}
+
+// The histogram indices of the symbols a token is coded with, and how many there are
+fn token_symbols(
+    token: Token,
+    alphabets: Alphabets,
+    distances: &DistanceCodes,
+) -> ([usize; 4], usize) {
+    let [green, red, blue, alpha, distance] = alphabets.ranges().map(|range| range.start);
+    match token {
+        Token::Literal(argb) => (
+            [
+                green + (argb >> 8 & 0xff) as usize,
+                red + (argb >> 16 & 0xff) as usize,
+                blue + (argb & 0xff) as usize,
+                alpha + (argb >> 24) as usize,
+            ],
+            4,
+        ),
+        Token::Cache(key) => (
+            [
+                green + NUM_LITERALS + NUM_LENGTH_CODES + key as usize,
+                0,
+                0,
+                0,
+            ],
+            1,
+        ),
+        Token::Copy {
+            length,
+            distance: pixels,
+        } => (
+            [
+                green + NUM_LITERALS + backward_refs::prefix(length).0,
+                distance + backward_refs::prefix(distances.code(pixels)).0,
+                0,
+                0,
+            ],
+            2,
+        ),
+    }
+}
+
+fn histograms(
+    tokens: &[Token],
+    width: usize,
+    alphabets: Alphabets,
+    entropy_image: &EntropyImage,
+    distances: &DistanceCodes,
+) -> Vec<Vec<u32>> {
+    let mut histograms = vec![vec![0; alphabets.len()]; entropy_image.count()];
+    let mut position = 0;
+    for &token in tokens {
+        let histogram = &mut histograms[entropy_image.group(position, width)];
+        let (symbols, count) = token_symbols(token, alphabets, distances);
+        for &symbol in &symbols[..count] {
+            histogram[symbol] += 1;
+        }
+        position += token.len();
+    }
+    histograms
+}
+
+// Bits per symbol estimated from a histogram. Unseen symbols would lengthen the code, so they're
+// priced as rarer than the rarest one.
+fn estimate_costs(histogram: &[u32], alphabets: Alphabets) -> Vec<f64> {
+    let mut costs = vec![0.0; histogram.len()];
+    for range in alphabets.ranges() {
+        let total: u64 = histogram[range.clone()].iter().map(|&c| c as u64).sum();
+        for symbol in range {
+            costs[symbol] =
+                (total as f64 + 1.0).log2() - (histogram[symbol] as f64).max(0.25).log2();
+        }
+    }
+    costs
+}
+
+fn group_codes(histogram: &[u32], alphabets: Alphabets) -> [Code; 5] {
+    alphabets.ranges().map(|range| Code::new(&histogram[range]))
+}
+
+// The exact size of a group's prefix codes and the symbols they code, without extra bits
+fn group_cost(histogram: &[u32], alphabets: Alphabets) -> u64 {
+    let mut writer = BitWriter::default();
+    let codes = group_codes(histogram, alphabets);
+    let mut cost = 0;
+    for (code, range) in codes.iter().zip(alphabets.ranges()) {
+        code.write(&mut writer);
+        cost += code.cost(&histogram[range]);
+    }
+    cost + writer.len()
+}
+
+// Writes the prefix codes and then the tokens
+fn write_tokens(
+    writer: &mut BitWriter,
+    tokens: &[Token],
+    width: usize,
+    alphabets: Alphabets,
+    entropy_image: &EntropyImage,
+    distances: &DistanceCodes,
+) {
+    let groups: Vec<[Code; 5]> = histograms(tokens, width, alphabets, entropy_image, distances)
+        .iter()
+        .map(|histogram| group_codes(histogram, alphabets))
+        .collect();
+    for code in groups.iter().flatten() {
+        code.write(writer);
+    }
+
+    let mut position = 0;
+    for &token in tokens {
+        let [green, red, blue, alpha, distance] = &groups[entropy_image.group(position, width)];
+        match token {
+            Token::Literal(argb) => {
+                green.write_symbol(writer, (argb >> 8 & 0xff) as usize);
+                red.write_symbol(writer, (argb >> 16 & 0xff) as usize);
+                blue.write_symbol(writer, (argb & 0xff) as usize);
+                alpha.write_symbol(writer, (argb >> 24) as usize);
+            }
+            Token::Cache(key) => {
+                green.write_symbol(writer, NUM_LITERALS + NUM_LENGTH_CODES + key as usize)
+            }
+            Token::Copy {
+                length,
+                distance: pixels,
+            } => {
+                let (prefix, extra_bits, extra) = backward_refs::prefix(length);
+                green.write_symbol(writer, NUM_LITERALS + prefix);
+                writer.write(extra, extra_bits);
+                let (prefix, extra_bits, extra) = backward_refs::prefix(distances.code(pixels));
+                distance.write_symbol(writer, prefix);
+                writer.write(extra, extra_bits);
+            }
+        }
+        position += token.len();
+    }
+}
+
+// Entropy images are coded as images of their own, without a color cache or groups
+fn write_sub_image(writer: &mut BitWriter, pixels: &[u32], width: usize) {
+    writer.write(0, 1);
+    let distances = DistanceCodes::new(width);
+    let finder = MatchFinder::new(pixels, &distances, 16);
+    let tokens = backward_refs::greedy(pixels, &finder, &[]);
+    let alphabets = Alphabets { cache_bits: 0 };
+    write_tokens(
+        writer,
+        &tokens,
+        width,
+        alphabets,
+        &EntropyImage::single(),
+        &distances,
+    );
+}
+
+fn sub_image_cost(pixels: &[u32], width: usize) -> u64 {
+    let mut writer = BitWriter::default();
+    write_sub_image(&mut writer, pixels, width);
+    writer.len()
+}
+
+fn write_image(
+    writer: &mut BitWriter,
+    tokens: &[Token],
+    width: usize,
+    alphabets: Alphabets,
+    entropy_image: &EntropyImage,
+    distances: &DistanceCodes,
+) {
+    if alphabets.cache_bits > 0 {
+        writer.write(1, 1);
+        writer.write(alphabets.cache_bits, 4);
+    } else {
+        writer.write(0, 1);
+    }
+    if entropy_image.bits > 0 {
+        writer.write(1, 1);
+        writer.write(entropy_image.bits - 2, 3);
+        write_sub_image(
+            writer,
+            &entropy_image.pixels(),
+            entropy_image.blocks_per_row,
+        );
+    } else {
+        writer.write(0, 1);
+    }
+    write_tokens(writer, tokens, width, alphabets, entropy_image, distances);
+}
+
+// Shannon entropy of a histogram, in bits
+fn entropy(histogram: &[u32]) -> f64 {
+    let total: u32 = histogram.iter().sum();
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| count as f64 * (total as f64 / count as f64).log2())
+        .sum()
+}
+
+// Subtracting green pays off when red and blue follow green, as in grayscale
+fn should_subtract_green(pixels: &[u32]) -> bool {
+    let mut plain = vec![[0; 256]; 2];
+    let mut subtracted = vec![[0; 256]; 2];
+    for &argb in pixels {
+        let [_, r, g, b] = argb.to_be_bytes();
+        plain[0][r as usize] += 1;
+        plain[1][b as usize] += 1;
+        subtracted[0][r.wrapping_sub(g) as usize] += 1;
+        subtracted[1][b.wrapping_sub(g) as usize] += 1;
+    }
+    let cost = |histograms: &[[u32; 256]]| histograms.iter().map(|h| entropy(h)).sum::<f64>();
+    cost(&subtracted) < cost(&plain)
+}
+
+fn subtract_green(pixels: &mut [u32]) {
+    for argb in pixels {
+        let [a, r, g, b] = argb.to_be_bytes();
+        *argb = u32::from_be_bytes([a, r.wrapping_sub(g), g, b.wrapping_sub(g)]);
+    }
+}
+
+// Estimates the size of literals with each color cache size, keeping the backward references
+fn best_cache_bits(pixels: &[u32], tokens: &[Token]) -> u32 {
+    (0..=MAX_CACHE_BITS)
+        .min_by_key(|&cache_bits| {
+            let hits = backward_refs::cache_hits(pixels, cache_bits);
+            let mut histograms = vec![vec![0; 256]; 4];
+            histograms[0].resize(NUM_LITERALS + NUM_LENGTH_CODES + (1 << cache_bits), 0);
+            let mut position = 0;
+            for &token in tokens {
+                if let Token::Literal(argb) = token {
+                    match hits[position] {
+                        Some(key) => {
+                            histograms[0][NUM_LITERALS + NUM_LENGTH_CODES + key as usize] += 1
+                        }
+                        None => {
+                            let [a, r, g, b] = argb.to_be_bytes();
+                            histograms[0][g as usize] += 1;
+                            histograms[1][r as usize] += 1;
+                            histograms[2][b as usize] += 1;
+                            histograms[3][a as usize] += 1;
+                        }
+                    }
+                }
+                position += token.len();
+            }
+            // Every used symbol costs a few bits in the code lengths
+            let used = histograms[0].iter().filter(|&&count| count > 0).count();
+            let bits: f64 = histograms.iter().map(|h| entropy(h)).sum();
+            (bits + 4.0 * used as f64) as u64
+        })
+        .unwrap()
+}
+
+fn riff(vp8l: Vec<u8>) -> Vec<u8> {
+    let padded = vp8l.len() + vp8l.len() % 2;
+    let mut output = Vec::with_capacity(20 + padded);
+    output.extend(b"RIFF");
+    output.extend((12 + padded as u32).to_le_bytes());
+    output.extend(b"WEBPVP8L");
+    output.extend((vp8l.len() as u32).to_le_bytes());
+    output.extend(&vp8l);
+    output.resize(20 + padded, 0);
+    output
+}
+
+// Encodes ARGB pixels, row by row, as a lossless WebP file. Effort ranges from 0 to 6, like
+// libwebp's method.
+pub fn encode(mut pixels: Vec<u32>, width: u32, height: u32, effort: i32) -> Vec<u8> {
+    let effort = effort.clamp(0, 6) as u32;
+    let alpha_is_used = pixels.iter().any(|&argb| argb >> 24 != 0xff);
+    let green_subtracted = should_subtract_green(&pixels);
+    if green_subtracted {
+        subtract_green(&mut pixels);
+    }
+
+    let width = width as usize;
+    let distances = DistanceCodes::new(width);
+    let finder = MatchFinder::new(&pixels, &distances, 16 << effort);
+    let cache_bits = best_cache_bits(&pixels, &backward_refs::greedy(&pixels, &finder, &[]));
+    let alphabets = Alphabets { cache_bits };
+    let hits = backward_refs::cache_hits(&pixels, cache_bits);
+    // Smaller blocks pay off less often and take longer to cluster
+    let bits_range = if effort >= 5 { 3..=7 } else { 4..=6 };
+
+    // Start from literal statistics, with every length and distance prefix equally likely
+    let mut histogram = vec![0; alphabets.len()];
+    for &argb in &pixels {
+        let (symbols, count) = token_symbols(Token::Literal(argb), alphabets, &distances);
+        for &symbol in &symbols[..count] {
+            histogram[symbol] += 1;
+        }
+    }
+    histogram[NUM_LITERALS..NUM_LITERALS + NUM_LENGTH_CODES]
+        .fill((pixels.len() / INITIAL_LENGTH_RARITY) as u32 + 1);
+    histogram[alphabets.ranges()[4].clone()].fill(1);
+    let single = EntropyImage::single();
+    let model = backward_refs::CostModel::new(&[histogram], alphabets, &single, width);
+    let mut tokens = backward_refs::optimal(&pixels, &finder, &hits, &distances, &model);
+
+    let write = |tokens: &[Token], entropy_image: &EntropyImage| {
+        let mut writer = BitWriter::default();
+        writer.write(SIGNATURE, 8);
+        writer.write(width as u32 - 1, 14);
+        writer.write(height - 1, 14);
+        writer.write(alpha_is_used as u32, 1);
+        writer.write(0, 3);
+        if green_subtracted {
+            writer.write(1, 1);
+            writer.write(SUBTRACT_GREEN, 2);
+        }
+        writer.write(0, 1);
+        write_image(
+            &mut writer,
+            tokens,
+            width,
+            alphabets,
+            entropy_image,
+            &distances,
+        );
+        writer.finish()
+    };
+
+    // In tiny images, backward references don't pay for their prefix codes
+    let literals: Vec<Token> = pixels
+        .iter()
+        .zip(&hits)
+        .map(|(&argb, hit)| hit.map_or(Token::Literal(argb), Token::Cache))
+        .collect();
+    let mut best = write(&literals, &EntropyImage::single());
+    for iteration in 0..=effort {
+        let entropy_image =
+            cluster::search(&tokens, width, alphabets, &distances, bits_range.clone());
+        let vp8l = write(&tokens, &entropy_image);
+        if vp8l.len() < best.len() {
+            best = vp8l;
+        }
+        if iteration == effort {
+            break;
+        }
+
+        // Parse again with the costs of this parse
+        let histograms = histograms(&tokens, width, alphabets, &entropy_image, &distances);
+        let model = backward_refs::CostModel::new(&histograms, alphabets, &entropy_image, width);
+        tokens = backward_refs::optimal(&pixels, &finder, &hits, &distances, &model);
+    }
+    riff(best)
+}
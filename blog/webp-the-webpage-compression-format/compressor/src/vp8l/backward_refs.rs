@@ -0,0 +1,364 @@
+// LZ77 as VP8L does it: backward references with 2D distance codes, and a color cache that stores
+// recently seen pixels. Pixels are turned into tokens either greedily or by an optimal parse under a
+// cost model.
+
+use super::cluster::EntropyImage;
+use super::{estimate_costs, Alphabets};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+pub const MAX_LENGTH: usize = 4096;
+const WINDOW_SIZE: usize = (1 << 20) - 120;
+// Matches at least this long are taken as they are during the optimal parse, as considering every
+// shorter length at every position inside repetitive data is quadratic
+const LONG_MATCH: usize = 512;
+const MAX_HASH_BITS: u32 = 18;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Token {
+    Literal(u32),
+    // A color cache hit, by key
+    Cache(u32),
+    // The distance is in pixels; it's turned into a distance code when written
+    Copy { length: u32, distance: u32 },
+}
+
+impl Token {
+    pub fn len(self) -> usize {
+        match self {
+            Self::Copy { length, .. } => length as usize,
+            _ => 1,
+        }
+    }
+}
+
+// Lengths and distance codes are stored as a prefix symbol followed by extra bits. Returns the
+// prefix, the number of extra bits and their value.
+pub fn prefix(value: u32) -> (usize, u32, u32) {
+    let value = value - 1;
+    if value < 4 {
+        return (value as usize, 0, 0);
+    }
+    let highest_bit = value.ilog2();
+    let second_bit = (value >> (highest_bit - 1)) & 1;
+    let extra_bits = highest_bit - 1;
+    (
+        (2 * highest_bit + second_bit) as usize,
+        extra_bits,
+        value & ((1 << extra_bits) - 1),
+    )
+}
+
+// The (x, y) offsets that distance codes 1 to 120 stand for, nearest first. Larger codes store the
+// distance plus 120.
+#[rustfmt::skip]
+const PLANE_CODES: [(i8, i8); 120] = [
+    (0, 1), (1, 0), (1, 1), (-1, 1), (0, 2), (2, 0), (1, 2), (-1, 2), (2, 1), (-2, 1), (2, 2),
+    (-2, 2), (0, 3), (3, 0), (1, 3), (-1, 3), (3, 1), (-3, 1), (2, 3), (-2, 3), (3, 2), (-3, 2),
+    (0, 4), (4, 0), (1, 4), (-1, 4), (4, 1), (-4, 1), (3, 3), (-3, 3), (2, 4), (-2, 4), (4, 2),
+    (-4, 2), (0, 5), (3, 4), (-3, 4), (4, 3), (-4, 3), (5, 0), (1, 5), (-1, 5), (5, 1), (-5, 1),
+    (2, 5), (-2, 5), (5, 2), (-5, 2), (4, 4), (-4, 4), (3, 5), (-3, 5), (5, 3), (-5, 3), (0, 6),
+    (6, 0), (1, 6), (-1, 6), (6, 1), (-6, 1), (2, 6), (-2, 6), (6, 2), (-6, 2), (4, 5), (-4, 5),
+    (5, 4), (-5, 4), (3, 6), (-3, 6), (6, 3), (-6, 3), (0, 7), (7, 0), (1, 7), (-1, 7), (5, 5),
+    (-5, 5), (7, 1), (-7, 1), (4, 6), (-4, 6), (6, 4), (-6, 4), (2, 7), (-2, 7), (7, 2), (-7, 2),
+    (3, 7), (-3, 7), (7, 3), (-7, 3), (5, 6), (-5, 6), (6, 5), (-6, 5), (8, 0), (4, 7), (-4, 7),
+    (7, 4), (-7, 4), (8, 1), (8, 2), (6, 6), (-6, 6), (8, 3), (5, 7), (-5, 7), (7, 5), (-7, 5),
+    (8, 4), (6, 7), (-6, 7), (7, 6), (-7, 6), (8, 5), (7, 7), (-7, 7), (8, 6), (8, 7),
+];
+
+// Maps distances to distance codes for a given image width
+pub struct DistanceCodes(HashMap<u32, u32>);
+
+impl DistanceCodes {
+    pub fn new(width: usize) -> Self {
+        let mut codes = HashMap::new();
+        for (i, &(x, y)) in PLANE_CODES.iter().enumerate() {
+            let distance = x as i64 + y as i64 * width as i64;
+            if distance >= 1 {
+                codes.entry(distance as u32).or_insert(i as u32 + 1);
+            }
+        }
+        Self(codes)
+    }
+
+    pub fn code(&self, distance: u32) -> u32 {
+        self.0.get(&distance).copied().unwrap_or(distance + 120)
+    }
+
+    // The distances that have short codes, nearest first
+    fn plane_distances(&self) -> Vec<u32> {
+        let mut distances: Vec<u32> = self.0.keys().copied().collect();
+        distances.sort_unstable();
+        distances
+    }
+}
+
+pub fn cache_key(argb: u32, cache_bits: u32) -> u32 {
+    argb.wrapping_mul(0x1e35a7bd) >> (32 - cache_bits)
+}
+
+// Every decoded pixel goes through the color cache, so whether a pixel is in the cache doesn't
+// depend on how the image is parsed. Returns the key of each pixel that's a hit.
+pub fn cache_hits(pixels: &[u32], cache_bits: u32) -> Vec<Option<u32>> {
+    if cache_bits == 0 {
+        return vec![None; pixels.len()];
+    }
+    // The decoder's cache starts zeroed, so transparent black hits right away
+    let mut cache = vec![0; 1 << cache_bits];
+    pixels
+        .iter()
+        .map(|&argb| {
+            let key = cache_key(argb, cache_bits);
+            let hit = cache[key as usize] == argb;
+            cache[key as usize] = argb;
+            hit.then_some(key)
+        })
+        .collect()
+}
+
+pub struct MatchFinder<'a> {
+    pixels: &'a [u32],
+    // The previous position with the same hash of two pixels
+    previous: Vec<u32>,
+    plane_distances: Vec<u32>,
+    chain_length: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    pub fn new(pixels: &'a [u32], distances: &DistanceCodes, chain_length: usize) -> Self {
+        // Entropy images are tiny, and they're encoded many times during the search
+        let hash_bits = pixels.len().max(2).ilog2().min(MAX_HASH_BITS);
+        let mut head = vec![u32::MAX; 1 << hash_bits];
+        let previous = (0..pixels.len())
+            .map(|i| {
+                if i + 1 == pixels.len() {
+                    return u32::MAX;
+                }
+                let hash = (pixels[i].wrapping_mul(0x9e3779b1)
+                    ^ pixels[i + 1].wrapping_mul(0x85ebca6b).rotate_left(13))
+                    >> (32 - hash_bits);
+                std::mem::replace(&mut head[hash as usize], i as u32)
+            })
+            .collect();
+        Self {
+            pixels,
+            previous,
+            plane_distances: distances.plane_distances(),
+            chain_length,
+        }
+    }
+
+    fn match_length(&self, position: usize, distance: usize, max_length: usize) -> usize {
+        self.pixels[position..position + max_length]
+            .iter()
+            .zip(&self.pixels[position - distance..])
+            .take_while(|(a, b)| a == b)
+            .count()
+    }
+
+    // Collects (distance, length) pairs of matches at the position: every distance with a short
+    // code, and the hash chain matches that are longer than all nearer ones
+    pub fn find(&self, position: usize, matches: &mut Vec<(u32, u32)>) {
+        matches.clear();
+        let max_length = MAX_LENGTH.min(self.pixels.len() - position);
+        for &distance in &self.plane_distances {
+            if distance as usize > position {
+                break;
+            }
+            let length = self.match_length(position, distance as usize, max_length);
+            if length > 0 {
+                matches.push((distance, length as u32));
+            }
+        }
+
+        let mut best = 1;
+        let mut candidate = self.previous[position];
+        for _ in 0..self.chain_length {
+            if candidate == u32::MAX || position - candidate as usize > WINDOW_SIZE {
+                break;
+            }
+            let distance = position - candidate as usize;
+            let length = self.match_length(position, distance, max_length);
+            if length > best {
+                best = length;
+                matches.push((distance as u32, length as u32));
+                if length == max_length {
+                    break;
+                }
+            }
+            candidate = self.previous[candidate as usize];
+        }
+    }
+}
+
+// Takes the longest match wherever there is one
+pub fn greedy(pixels: &[u32], finder: &MatchFinder, hits: &[Option<u32>]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut matches = Vec::new();
+    let mut position = 0;
+    while position < pixels.len() {
+        finder.find(position, &mut matches);
+        let longest = matches
+            .iter()
+            .max_by_key(|&&(distance, length)| (length, Reverse(distance)));
+        let token = match (longest, hits.get(position)) {
+            (Some(&(distance, length)), _) if length >= 2 => Token::Copy { length, distance },
+            (_, Some(&Some(key))) => Token::Cache(key),
+            _ => Token::Literal(pixels[position]),
+        };
+        tokens.push(token);
+        position += token.len();
+    }
+    tokens
+}
+
+// Bit costs of every symbol in every group, estimated from the histograms of a previous parse
+pub struct CostModel<'a> {
+    alphabets: Alphabets,
+    entropy_image: &'a EntropyImage,
+    width: usize,
+    symbols: Vec<Vec<f64>>,
+    lengths: Vec<Vec<f64>>,
+}
+
+impl<'a> CostModel<'a> {
+    pub fn new(
+        histograms: &[Vec<u32>],
+        alphabets: Alphabets,
+        entropy_image: &'a EntropyImage,
+        width: usize,
+    ) -> Self {
+        let symbols: Vec<Vec<f64>> = histograms
+            .iter()
+            .map(|histogram| estimate_costs(histogram, alphabets))
+            .collect();
+        let green = alphabets.ranges()[0].start;
+        let lengths = symbols
+            .iter()
+            .map(|costs| {
+                (0..=MAX_LENGTH as u32)
+                    .map(|length| {
+                        if length == 0 {
+                            return 0.0;
+                        }
+                        let (prefix, extra_bits, _) = prefix(length);
+                        costs[green + 256 + prefix] + extra_bits as f64
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            alphabets,
+            entropy_image,
+            width,
+            symbols,
+            lengths,
+        }
+    }
+}
+
+// Finds the cheapest sequence of tokens under the cost model by dynamic programming over
+// positions. For every length, only the match with the cheapest distance is considered.
+pub fn optimal(
+    pixels: &[u32],
+    finder: &MatchFinder,
+    hits: &[Option<u32>],
+    distances: &DistanceCodes,
+    model: &CostModel,
+) -> Vec<Token> {
+    let [green, red, blue, alpha, distance_codes] = model.alphabets.ranges().map(|r| r.start);
+    let cache = green + 256 + 24;
+    let n = pixels.len();
+    let mut costs = vec![f64::INFINITY; n + 1];
+    // The token that ends at each position
+    let mut steps = vec![Token::Literal(0); n + 1];
+    costs[0] = 0.0;
+
+    let mut matches = Vec::new();
+    let mut candidates = Vec::new();
+    let mut skip_until = 0;
+    for position in 0..n {
+        let cost = costs[position];
+        if position < skip_until || cost.is_infinite() {
+            continue;
+        }
+        let group = model.entropy_image.group(position, model.width);
+        let symbols = &model.symbols[group];
+        let mut relax = |end: usize, step_cost: f64, token: Token| {
+            if cost + step_cost < costs[end] {
+                costs[end] = cost + step_cost;
+                steps[end] = token;
+            }
+        };
+
+        let argb = pixels[position];
+        let literal = symbols[green + (argb >> 8 & 0xff) as usize]
+            + symbols[red + (argb >> 16 & 0xff) as usize]
+            + symbols[blue + (argb & 0xff) as usize]
+            + symbols[alpha + (argb >> 24) as usize];
+        relax(position + 1, literal, Token::Literal(argb));
+        if let Some(key) = hits[position] {
+            relax(
+                position + 1,
+                symbols[cache + key as usize],
+                Token::Cache(key),
+            );
+        }
+
+        finder.find(position, &mut matches);
+        candidates.clear();
+        candidates.extend(matches.iter().map(|&(distance, length)| {
+            let (prefix, extra_bits, _) = prefix(distances.code(distance));
+            let cost = symbols[distance_codes + prefix] + extra_bits as f64;
+            (cost, distance, length as usize)
+        }));
+        let lengths = &model.lengths[group];
+        if let Some(&(distance_cost, distance, length)) = candidates
+            .iter()
+            .filter(|&&(_, _, length)| length >= LONG_MATCH)
+            .min_by(|a, b| b.2.cmp(&a.2).then(a.0.total_cmp(&b.0)))
+        {
+            relax(
+                position + length,
+                distance_cost + lengths[length],
+                Token::Copy {
+                    length: length as u32,
+                    distance,
+                },
+            );
+            skip_until = position + length;
+            continue;
+        }
+        // A length is best copied from the cheapest distance that matches at least that long
+        candidates.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        let mut covered = 0;
+        for &(distance_cost, distance, length) in &candidates {
+            let new_lengths = lengths
+                .iter()
+                .enumerate()
+                .take(length + 1)
+                .skip(covered + 1);
+            for (length, &length_cost) in new_lengths {
+                relax(
+                    position + length,
+                    distance_cost + length_cost,
+                    Token::Copy {
+                        length: length as u32,
+                        distance,
+                    },
+                );
+            }
+            covered = covered.max(length);
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut position = n;
+    while position > 0 {
+        let token = steps[position];
+        tokens.push(token);
+        position -= token.len();
+    }
+    tokens.reverse();
+    tokens
+}
@@ -0,0 +1,243 @@
+// Prefix code groups. The entropy image assigns one of several groups of prefix codes to each block
+// of 2^bits x 2^bits pixels. Pages interleave markup, CSS, JavaScript and SVG, whose statistics
+// differ, so blocks are clustered by their histograms: k-means under cross-entropy, growing one group
+// at a time from the block that fits its group worst. The block size and the number of groups are
+// picked by the resulting size, which includes the prefix codes and the entropy image.
+
+use super::backward_refs::{DistanceCodes, Token};
+use super::{estimate_costs, group_cost, sub_image_cost, token_symbols, Alphabets};
+use std::ops::RangeInclusive;
+
+// Group indices are stored in the green channel of the entropy image
+const MAX_GROUPS: usize = 256;
+const LLOYD_ITERATIONS: usize = 3;
+// How many groups are added without improvement before giving up
+const PATIENCE: usize = 3;
+
+pub struct EntropyImage {
+    pub bits: u32,
+    pub blocks_per_row: usize,
+    pub groups: Vec<u16>,
+}
+
+impl EntropyImage {
+    // No entropy image at all, every pixel uses the only group
+    pub fn single() -> Self {
+        Self {
+            bits: 0,
+            blocks_per_row: 1,
+            groups: vec![0],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        *self.groups.iter().max().unwrap() as usize + 1
+    }
+
+    pub fn group(&self, position: usize, width: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let x = (position % width) >> self.bits;
+        let y = (position / width) >> self.bits;
+        self.groups[y * self.blocks_per_row + x] as usize
+    }
+
+    // The meta prefix code of a block is stored in red and green
+    pub fn pixels(&self) -> Vec<u32> {
+        self.groups
+            .iter()
+            .map(|&group| (group as u32) << 8)
+            .collect()
+    }
+}
+
+// Sparse histograms of the symbols that start in each block
+fn block_histograms(
+    tokens: &[Token],
+    width: usize,
+    height: usize,
+    bits: u32,
+    alphabets: Alphabets,
+    distances: &DistanceCodes,
+) -> Vec<Vec<(u32, u32)>> {
+    let blocks_per_row = width.div_ceil(1 << bits);
+    let blocks_per_column = height.div_ceil(1 << bits);
+    let mut pairs = Vec::new();
+    let mut position = 0;
+    for &token in tokens {
+        let block = ((position / width) >> bits) * blocks_per_row + ((position % width) >> bits);
+        let (symbols, count) = token_symbols(token, alphabets, distances);
+        pairs.extend(symbols[..count].iter().map(|&symbol| (block, symbol)));
+        position += token.len();
+    }
+    pairs.sort_unstable();
+
+    // Blocks covered by a single backward reference have no symbols of their own
+    let mut blocks = vec![Vec::new(); blocks_per_row * blocks_per_column];
+    for run in pairs.chunk_by(|a, b| a == b) {
+        let (block, symbol) = run[0];
+        blocks[block].push((symbol as u32, run.len() as u32));
+    }
+    blocks
+}
+
+fn cross_entropy(block: &[(u32, u32)], costs: &[f64]) -> f64 {
+    block
+        .iter()
+        .map(|&(symbol, count)| count as f64 * costs[symbol as usize])
+        .sum()
+}
+
+// Reassigns blocks to the groups that code them cheapest, then recomputes the groups. Empty groups
+// are dropped.
+fn lloyd(
+    blocks: &[Vec<(u32, u32)>],
+    centroids: &mut Vec<Vec<u32>>,
+    assignment: &mut [u16],
+    alphabets: Alphabets,
+) {
+    for _ in 0..LLOYD_ITERATIONS {
+        let costs: Vec<Vec<f64>> = centroids
+            .iter()
+            .map(|centroid| estimate_costs(centroid, alphabets))
+            .collect();
+        for (block, group) in blocks.iter().zip(assignment.iter_mut()) {
+            *group = (0..costs.len())
+                .min_by(|&a, &b| {
+                    cross_entropy(block, &costs[a]).total_cmp(&cross_entropy(block, &costs[b]))
+                })
+                .unwrap() as u16;
+        }
+
+        let mut renumbered = vec![u16::MAX; centroids.len()];
+        let mut count = 0;
+        for group in assignment.iter_mut() {
+            if renumbered[*group as usize] == u16::MAX {
+                renumbered[*group as usize] = count;
+                count += 1;
+            }
+            *group = renumbered[*group as usize];
+        }
+        *centroids = vec![vec![0; alphabets.len()]; count as usize];
+        for (block, &group) in blocks.iter().zip(assignment.iter()) {
+            for &(symbol, count) in block {
+                centroids[group as usize][symbol as usize] += count;
+            }
+        }
+    }
+}
+
+// The block that's coded worst by its group compared to a code of its own
+fn worst_fit(
+    blocks: &[Vec<(u32, u32)>],
+    centroids: &[Vec<u32>],
+    assignment: &[u16],
+    alphabets: Alphabets,
+) -> usize {
+    let costs: Vec<Vec<f64>> = centroids
+        .iter()
+        .map(|centroid| estimate_costs(centroid, alphabets))
+        .collect();
+    let ranges = alphabets.ranges();
+    let excess = |(block, &group): (&Vec<(u32, u32)>, &u16)| {
+        let mut totals = [0; 5];
+        for &(symbol, count) in block {
+            totals[ranges
+                .iter()
+                .position(|r| r.contains(&(symbol as usize)))
+                .unwrap()] += count;
+        }
+        let own: f64 = block
+            .iter()
+            .map(|&(symbol, count)| {
+                let alphabet = ranges
+                    .iter()
+                    .position(|r| r.contains(&(symbol as usize)))
+                    .unwrap();
+                count as f64 * (totals[alphabet] as f64 / count as f64).log2()
+            })
+            .sum();
+        cross_entropy(block, &costs[group as usize]) - own
+    };
+    blocks
+        .iter()
+        .zip(assignment)
+        .map(excess)
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+        .0
+}
+
+fn cost(centroids: &[Vec<u32>], entropy_image: &EntropyImage, alphabets: Alphabets) -> u64 {
+    let groups: u64 = centroids
+        .iter()
+        .map(|centroid| group_cost(centroid, alphabets))
+        .sum();
+    if entropy_image.bits == 0 {
+        return groups;
+    }
+    groups + sub_image_cost(&entropy_image.pixels(), entropy_image.blocks_per_row)
+}
+
+// Picks the block size and the groups that make the image smallest
+pub fn search(
+    tokens: &[Token],
+    width: usize,
+    alphabets: Alphabets,
+    distances: &DistanceCodes,
+    bits_range: RangeInclusive<u32>,
+) -> EntropyImage {
+    let mut total = vec![0; alphabets.len()];
+    let mut position = 0;
+    for &token in tokens {
+        let (symbols, count) = token_symbols(token, alphabets, distances);
+        for &symbol in &symbols[..count] {
+            total[symbol] += 1;
+        }
+        position += token.len();
+    }
+    let height = position / width;
+    let mut best_image = EntropyImage::single();
+    let mut best_cost = cost(&[total.clone()], &best_image, alphabets);
+
+    for bits in bits_range {
+        let blocks = block_histograms(tokens, width, height, bits, alphabets, distances);
+        if blocks.len() < 2 {
+            continue;
+        }
+        let mut centroids = vec![total.clone()];
+        let mut assignment = vec![0; blocks.len()];
+        let mut stale = 0;
+        while centroids.len() < MAX_GROUPS.min(blocks.len()) && stale < PATIENCE {
+            let seed = worst_fit(&blocks, &centroids, &assignment, alphabets);
+            let mut centroid = vec![0; alphabets.len()];
+            for &(symbol, count) in &blocks[seed] {
+                centroid[symbol as usize] = count;
+            }
+            centroids.push(centroid);
+            let groups_before = centroids.len();
+            lloyd(&blocks, &mut centroids, &mut assignment, alphabets);
+
+            let image = EntropyImage {
+                bits,
+                blocks_per_row: width.div_ceil(1 << bits),
+                groups: assignment.clone(),
+            };
+            let image_cost = cost(&centroids, &image, alphabets);
+            if image_cost < best_cost {
+                best_cost = image_cost;
+                best_image = image;
+                stale = 0;
+            } else {
+                stale += 1;
+            }
+            // The new group didn't survive, so there's nothing better to find
+            if centroids.len() < groups_before {
+                break;
+            }
+        }
+    }
+    best_image
+}
@@ -0,0 +1,263 @@
+// Prefix codes as VP8L stores them: canonical, at most 15 bits long, and described by their code
+// lengths, which are run-length encoded and compressed with a second prefix code.
+
+use super::BitWriter;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+// The order in which the lengths of the code length code are stored, most common first
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+// Code length 16 repeats the previous non-zero length, which starts at 8
+const INITIAL_REPEATED_LENGTH: u8 = 8;
+
+pub struct Code {
+    pub lengths: Vec<u8>,
+    codes: Vec<u16>,
+    // The symbol of a code with a single symbol, which takes no bits
+    lone: Option<usize>,
+}
+
+impl Code {
+    pub fn new(counts: &[u32]) -> Self {
+        Self::limited(counts, MAX_CODE_LENGTH)
+    }
+
+    fn limited(counts: &[u32], max_length: u8) -> Self {
+        let used: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] > 0).collect();
+        let mut lengths = vec![0; counts.len()];
+        let mut lone = None;
+        match used[..] {
+            [] => {}
+            // Simple codes can only store the first 256 symbols. Anything else needs two symbols,
+            // as a normal code with one symbol is invalid.
+            [symbol] if symbol < 256 => lone = Some(symbol),
+            [symbol] => {
+                lengths[symbol] = 1;
+                lengths[0] = 1;
+            }
+            _ => lengths = huffman_lengths(counts, max_length),
+        }
+        let codes = canonical_codes(&lengths);
+        Self {
+            lengths,
+            codes,
+            lone,
+        }
+    }
+
+    // The number of bits needed to store symbols with these counts
+    pub fn cost(&self, counts: &[u32]) -> u64 {
+        counts
+            .iter()
+            .zip(&self.lengths)
+            .map(|(&count, &length)| count as u64 * length as u64)
+            .sum()
+    }
+
+    pub fn write_symbol(&self, writer: &mut BitWriter, symbol: usize) {
+        debug_assert!(self.lengths[symbol] > 0 || self.lone == Some(symbol));
+        writer.write(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+    }
+
+    pub fn write(&self, writer: &mut BitWriter) {
+        let used: Vec<usize> = match self.lone {
+            Some(symbol) => vec![symbol],
+            None => (0..self.lengths.len())
+                .filter(|&i| self.lengths[i] > 0)
+                .collect(),
+        };
+        match used[..] {
+            // Unused codes still have to be stored
+            [] => self.write_simple(writer, &[0]),
+            [_] | [_, _] if used.iter().all(|&symbol| symbol < 256) => {
+                self.write_simple(writer, &used)
+            }
+            _ => self.write_normal(writer),
+        }
+    }
+
+    fn write_simple(&self, writer: &mut BitWriter, symbols: &[usize]) {
+        writer.write(1, 1);
+        writer.write(symbols.len() as u32 - 1, 1);
+        if symbols[0] < 2 {
+            writer.write(0, 1);
+            writer.write(symbols[0] as u32, 1);
+        } else {
+            writer.write(1, 1);
+            writer.write(symbols[0] as u32, 8);
+        }
+        if let Some(&symbol) = symbols.get(1) {
+            writer.write(symbol as u32, 8);
+        }
+    }
+
+    // Trailing zero lengths can be left out by storing how many code length symbols follow, which
+    // is only sometimes shorter
+    fn write_normal(&self, writer: &mut BitWriter) {
+        let trim = [false, true].into_iter().min_by_key(|&trim| {
+            let mut scratch = BitWriter::default();
+            self.write_lengths(&mut scratch, trim);
+            scratch.len()
+        });
+        self.write_lengths(writer, trim.unwrap());
+    }
+
+    fn write_lengths(&self, writer: &mut BitWriter, trim: bool) {
+        let used = if trim {
+            self.lengths.iter().rposition(|&length| length > 0).unwrap() + 1
+        } else {
+            self.lengths.len()
+        };
+        let tokens = run_length_encode(&self.lengths[..used]);
+        let mut counts = [0; 19];
+        for &(symbol, _) in &tokens {
+            counts[symbol as usize] += 1;
+        }
+        // The code length code must have two symbols too
+        if counts.iter().filter(|&&count| count > 0).count() < 2 {
+            counts[(counts[0] > 0) as usize] += 1;
+        }
+        let code = Self::limited(&counts, MAX_CODE_LENGTH_CODE_LENGTH);
+
+        let stored = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code.lengths[symbol] > 0)
+            .map_or(0, |i| i + 1)
+            .max(4);
+        writer.write(0, 1);
+        writer.write(stored as u32 - 4, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..stored] {
+            writer.write(code.lengths[symbol] as u32, 3);
+        }
+        if trim {
+            // The count is stored minus 2 in 2 + 2 * n bits, where n takes 3 bits
+            let count = tokens.len() as u32 - 2;
+            let n = count.checked_ilog2().unwrap_or(0) / 2;
+            writer.write(1, 1);
+            writer.write(n, 3);
+            writer.write(count, 2 + 2 * n);
+        } else {
+            writer.write(0, 1);
+        }
+        for (symbol, extra) in tokens {
+            code.write_symbol(writer, symbol as usize);
+            match symbol {
+                16 => writer.write(extra as u32, 2),
+                17 => writer.write(extra as u32, 3),
+                18 => writer.write(extra as u32, 7),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Builds a Huffman tree and reads the code lengths off it. If the tree is too deep, rare symbols
+// are made more common until it fits, which keeps the code complete.
+fn huffman_lengths(counts: &[u32], max_length: u8) -> Vec<u8> {
+    let mut floor = 1;
+    loop {
+        let mut parents: Vec<usize> = Vec::new();
+        let mut heap = BinaryHeap::new();
+        let mut leaves = Vec::new();
+        for (symbol, &count) in counts.iter().enumerate() {
+            if count > 0 {
+                heap.push(Reverse((count.max(floor) as u64, parents.len())));
+                leaves.push((symbol, parents.len()));
+                parents.push(usize::MAX);
+            }
+        }
+        while heap.len() > 1 {
+            let Reverse((weight1, node1)) = heap.pop().unwrap();
+            let Reverse((weight2, node2)) = heap.pop().unwrap();
+            let parent = parents.len();
+            parents.push(usize::MAX);
+            parents[node1] = parent;
+            parents[node2] = parent;
+            heap.push(Reverse((weight1 + weight2, parent)));
+        }
+
+        // Parents are created after their children, so depths can be filled in from the root
+        let mut depths = vec![0u8; parents.len()];
+        for node in (0..parents.len()).rev() {
+            if parents[node] != usize::MAX {
+                depths[node] = depths[parents[node]] + 1;
+            }
+        }
+        if leaves.iter().all(|&(_, node)| depths[node] <= max_length) {
+            let mut lengths = vec![0; counts.len()];
+            for (symbol, node) in leaves {
+                lengths[symbol] = depths[node];
+            }
+            return lengths;
+        }
+        floor *= 2;
+    }
+}
+
+// Assigns codes in the order of length, then symbol, bit-reversed because the bit reader starts
+// from the least significant bit
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut length_counts = [0u16; MAX_CODE_LENGTH as usize + 1];
+    for &length in lengths {
+        length_counts[length as usize] += 1;
+    }
+    length_counts[0] = 0;
+    let mut next_code = [0u16; MAX_CODE_LENGTH as usize + 1];
+    let mut code = 0;
+    for length in 1..=MAX_CODE_LENGTH as usize {
+        code = (code + length_counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+    lengths
+        .iter()
+        .map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next_code[length as usize];
+            next_code[length as usize] += 1;
+            code.reverse_bits() >> (16 - length)
+        })
+        .collect()
+}
+
+// Turns code lengths into code length code symbols with their extra bits: 16 repeats the previous
+// non-zero length 3-6 times, 17 repeats zero 3-10 times, 18 repeats zero 11-138 times
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut tokens = Vec::new();
+    let mut previous = INITIAL_REPEATED_LENGTH;
+    let mut i = 0;
+    while i < lengths.len() {
+        let value = lengths[i];
+        let mut run = lengths[i..].iter().take_while(|&&l| l == value).count();
+        i += run;
+        if value == 0 {
+            while run >= 11 {
+                let repeat = run.min(138);
+                tokens.push((18, (repeat - 11) as u8));
+                run -= repeat;
+            }
+            if run >= 3 {
+                tokens.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else {
+            if value != previous {
+                tokens.push((value, 0));
+                previous = value;
+                run -= 1;
+            }
+            while run >= 3 {
+                let repeat = run.min(6);
+                tokens.push((16, (repeat - 3) as u8));
+                run -= repeat;
+            }
+        }
+        tokens.extend(std::iter::repeat_n((value, 0), run));
+    }
+    tokens
+}
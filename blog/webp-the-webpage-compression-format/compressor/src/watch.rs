@@ -0,0 +1,262 @@
+// Watch mode: polls a directory of generated pages, found like in site mode, and embeds every page
+// that changed in place, keeping the original as nojs.html next to it, as `embed` does.
+//
+// `npm run build` regenerates every page, not just the edited ones, so a page is only compressed
+// again if its contents differ from the original it was last compressed from. Otherwise, the
+// compressed page is just written back. Pages that are already compressed when watching starts
+// are recognized by their fallback, and pages are recognized as our own output by their
+// modification time, so writing them doesn't trigger a rebuild.
+
+use crate::embed::{self, Embedded};
+use crate::encoding::Encoding;
+use crate::error::{read_to_string, write};
+use crate::site::find_pages;
+use crate::Error;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const NOJS: &str = "nojs.html";
+
+// Changes whenever the file is written, unless it's rewritten with the same length within the
+// granularity of modification times. That's accepted: hashing every page on every poll would cost
+// far more, and the build writes pages well apart.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+fn stamp(path: &Path) -> Result<Stamp, Error> {
+    let metadata = std::fs::metadata(path).map_err(|error| Error::Io(path.to_path_buf(), error))?;
+    Ok(Stamp {
+        modified: metadata
+            .modified()
+            .map_err(|error| Error::Io(path.to_path_buf(), error))?,
+        len: metadata.len(),
+    })
+}
+
+// Identifies an original page without keeping it around
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Source {
+    checksum: u32,
+    len: usize,
+}
+
+impl Source {
+    fn of(html: &str) -> Self {
+        Self {
+            checksum: crc32fast::hash(html.as_bytes()),
+            len: html.len(),
+        }
+    }
+}
+
+struct Page {
+    // Of index.html when it was last read or written
+    stamp: Stamp,
+    // The original and the page compressed from it, if there is one
+    built: Option<(Source, String)>,
+    // Bytes transferred for the compressed page, or for the original if there is none
+    transfer: usize,
+}
+
+pub struct Watcher<'a> {
+    root: &'a Path,
+    repair: bool,
+    chunks: usize,
+    encoding: Encoding,
+    args: &'a crate::Options,
+    pages: HashMap<PathBuf, Page>,
+    // Pages that couldn't be read on the last poll, so that each failure is reported once
+    unreadable: HashSet<PathBuf>,
+    rebuilds: usize,
+}
+
+// What happened to a page in a rebuild
+enum Outcome {
+    Compressed(Embedded),
+    Restored,
+    // Compressed by someone else, e.g. `embed`
+    Embedded,
+}
+
+impl<'a> Watcher<'a> {
+    pub fn new(
+        root: &'a Path,
+        repair: bool,
+        chunks: usize,
+        encoding: Encoding,
+        args: &'a crate::Options,
+    ) -> Result<Self, Error> {
+        embed::check_repair(repair, args.layout)?;
+        embed::check_browser_support(args)?;
+        Ok(Self {
+            root,
+            repair,
+            chunks,
+            encoding,
+            args,
+            pages: HashMap::new(),
+            unreadable: HashSet::new(),
+            rebuilds: 0,
+        })
+    }
+
+    // The transfer size of a page that is already compressed, with its original in nojs.html
+    fn existing(path: &Path, html: &str) -> Option<(Source, usize)> {
+        let nojs = read_to_string(&path.with_file_name(NOJS)).ok()?;
+        let mut transfer = embed::gzipped_len(html.as_bytes());
+        let external = path.with_extension("webp");
+        let external_url = external.file_name()?.to_str()?;
+        if html.contains(&embed::preload(external_url)) {
+            transfer += std::fs::metadata(&external).ok()?.len() as usize;
+        }
+        Some((Source::of(&nojs), transfer))
+    }
+
+    fn rebuild(&mut self, path: &Path, html: &str) -> Result<Outcome, Error> {
+        if embed::is_embedded(html) {
+            return Ok(Outcome::Embedded);
+        }
+        let source = Source::of(html);
+        let built = self.pages.get(path).and_then(|page| page.built.as_ref());
+        if let Some((_, page)) = built.filter(|(built, _)| *built == source) {
+            write(path, page)?;
+            return Ok(Outcome::Restored);
+        }
+        let external = path.with_extension("webp");
+        let embedded = embed::embed_page(
+            html,
+            NOJS,
+            external.file_name().unwrap().to_str().unwrap(),
+            self.repair,
+            self.chunks,
+            self.encoding,
+            self.args,
+        )?;
+        write(&path.with_file_name(NOJS), html)?;
+        write(path, &embedded.page)?;
+        if let Some(images) = embedded.external_file() {
+            write(&external, images)?;
+        }
+        Ok(Outcome::Compressed(embedded))
+    }
+
+    // Looks for changed pages and rebuilds them, printing the transfer size of each compressed page
+    // and the change in the total. Returns whether anything was rebuilt.
+    pub fn poll(&mut self) -> Result<bool, Error> {
+        let mut paths = Vec::new();
+        find_pages(self.root, &mut paths)?;
+        self.pages.retain(|path, _| paths.contains(path));
+        self.unreadable.retain(|path| paths.contains(path));
+
+        let (mut compressed, mut restored, mut delta) = (0, 0, 0);
+        for path in paths {
+            let name = path.strip_prefix(self.root).unwrap().display().to_string();
+            // The page may be in the middle of being replaced, or broken until the next build, so
+            // it's retried on every poll
+            let html = stamp(&path).and_then(|before| {
+                let unchanged = self
+                    .pages
+                    .get(&path)
+                    .is_some_and(|page| page.stamp == before);
+                (!unchanged).then(|| read_to_string(&path)).transpose()
+            });
+            let html = match html {
+                Ok(Some(html)) => html,
+                Ok(None) => continue,
+                Err(error) => {
+                    // The error names the page
+                    if self.unreadable.insert(path) {
+                        eprintln!("error: {error}");
+                    }
+                    continue;
+                }
+            };
+            self.unreadable.remove(&path);
+            let outcome = self.rebuild(&path, &html);
+            let page = self.pages.remove(&path);
+            // What was served before: the last compressed page, or the original seen for the first
+            // time
+            let original = embed::gzipped_len(html.as_bytes());
+            let previous = page.as_ref().map(|page| page.transfer);
+            let built = page.and_then(|page| page.built);
+
+            let (built, transfer, previous) = match outcome {
+                Ok(Outcome::Compressed(embedded)) => {
+                    compressed += 1;
+                    let previous = previous.unwrap_or(original);
+                    println!(
+                        "{name}: {} -> {} bytes, {} bytes transferred with {} ({:+})",
+                        html.len(),
+                        embedded.page.len(),
+                        embedded.transfer,
+                        embedded.encoding,
+                        embedded.transfer as i64 - previous as i64,
+                    );
+                    let built = (Source::of(&html), embedded.page);
+                    (Some(built), embedded.transfer, previous)
+                }
+                Ok(Outcome::Restored) => {
+                    restored += 1;
+                    let previous = previous.unwrap_or(original);
+                    (built, previous, previous)
+                }
+                Ok(Outcome::Embedded) => {
+                    let (built, transfer) = match Self::existing(&path, &html) {
+                        Some((source, transfer)) => (Some((source, html)), transfer),
+                        None => (None, original),
+                    };
+                    (built, transfer, previous.unwrap_or(transfer))
+                }
+                // The original stays in place until the next change
+                Err(error) => {
+                    eprintln!("error: {name}: {error}");
+                    (built, original, previous.unwrap_or(original))
+                }
+            };
+            delta += transfer as i64 - previous as i64;
+            let Ok(stamp) = stamp(&path) else {
+                continue;
+            };
+            self.pages.insert(
+                path,
+                Page {
+                    stamp,
+                    built,
+                    transfer,
+                },
+            );
+        }
+
+        if compressed + restored == 0 {
+            return Ok(false);
+        }
+        self.rebuilds += 1;
+        let total: usize = self.pages.values().map(|page| page.transfer).sum();
+        println!(
+            "rebuild {}: {compressed} compressed, {restored} unchanged, {total} bytes transferred for {} pages ({delta:+})",
+            self.rebuilds,
+            self.pages.len(),
+        );
+        Ok(true)
+    }
+}
+
+pub fn run(
+    root: &Path,
+    interval: Duration,
+    repair: bool,
+    chunks: usize,
+    encoding: Encoding,
+    args: &crate::Options,
+) -> Result<(), Error> {
+    let mut watcher = Watcher::new(root, repair, chunks, encoding, args)?;
+    eprintln!("watching {}", root.display());
+    loop {
+        watcher.poll()?;
+        std::thread::sleep(interval);
+    }
+}
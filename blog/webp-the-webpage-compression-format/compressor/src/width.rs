@@ -0,0 +1,122 @@
+// VP8L predicts pixels from their neighbours above and uses 2D distance codes for backreferences,
+// so the image width matters a lot for compression.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// WebP images can't be larger than this in either dimension
+pub const MAX_DIMENSION: u32 = 16383;
+
+// Inputs that don't fit in this many pixels are split into tiles. This is the largest image the
+// default width allows, and it keeps libwebp's memory use reasonable.
+pub const MAX_TILE_PIXELS: u32 = 2048 * MAX_DIMENSION;
+// The tile size when streaming, which bounds memory use instead: libwebp needs about 35 bytes per
+// pixel, so about 70 MB for a tile this large rather than more than 1 GB
+pub const STREAM_TILE_PIXELS: u32 = 2048 * 1024;
+
+// How many of the most common line lengths are tried in auto mode
+const LINE_LENGTH_CANDIDATES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    // Try several widths and keep the smallest output
+    Auto,
+    Fixed(u32),
+}
+
+impl FromStr for Width {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s == "auto" {
+            return Ok(Self::Auto);
+        }
+        match s.parse() {
+            Ok(width @ 1..=MAX_DIMENSION) => Ok(Self::Fixed(width)),
+            _ => Err(format!(
+                "expected \"auto\" or a number from 1 to {MAX_DIMENSION}"
+            )),
+        }
+    }
+}
+
+// Umm... Nx2048?
+pub fn default_width(pixels: u32) -> u32 {
+    pixels.div_ceil(2048).max(1)
+}
+
+// Returns the most common distances between consecutive newlines. If the input is made of lines of
+// the same length, aligning them vertically lets the predictors and 2D backreferences work.
+fn common_line_lengths(data: &[u8]) -> Vec<u32> {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let mut line_start = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\n' {
+            *counts.entry((i + 1 - line_start) as u32).or_default() += 1;
+            line_start = i + 1;
+        }
+    }
+    let mut counts: Vec<(u32, usize)> = counts.into_iter().filter(|&(_, n)| n > 1).collect();
+    counts.sort_by_key(|&(length, n)| (std::cmp::Reverse(n), length));
+    counts
+        .into_iter()
+        .take(LINE_LENGTH_CANDIDATES)
+        .map(|(length, _)| length)
+        .collect()
+}
+
+// Lengths of all lines, including the newline
+fn line_lengths(data: &[u8]) -> Vec<usize> {
+    let mut lengths: Vec<usize> = data
+        .split_inclusive(|&b| b == b'\n')
+        .map(|line| line.len())
+        .collect();
+    lengths.sort();
+    lengths
+}
+
+// Width for the line-aligned transform such that `quantile` of the lines fit in one row
+fn line_width(lengths: &[usize], quantile: f64, stride: usize) -> u32 {
+    let length = lengths
+        .get((lengths.len() as f64 * quantile) as usize)
+        .or(lengths.last())
+        .copied()
+        .unwrap_or(1);
+    // The row length is stored in 16 bits
+    let max_width = MAX_DIMENSION.min(u16::MAX as u32 / stride as u32);
+    (length.div_ceil(stride) as u32).clamp(1, max_width)
+}
+
+pub fn default_line_width(data: &[u8], stride: usize) -> u32 {
+    line_width(&line_lengths(data), 0.9, stride)
+}
+
+// Longer rows fit more lines without wrapping, shorter ones waste less padding
+pub fn line_width_candidates(data: &[u8], stride: usize) -> Vec<u32> {
+    let lengths = line_lengths(data);
+    let mut widths: Vec<u32> = [0.5, 0.75, 0.9, 0.95, 0.99]
+        .into_iter()
+        .map(|quantile| line_width(&lengths, quantile, stride))
+        .collect();
+    widths.dedup();
+    widths
+}
+
+// Widths worth trying for an image of `pixels` pixels holding `data`, with `stride` consecutive
+// bytes per pixel
+pub fn candidates(data: &[u8], pixels: u32, stride: usize) -> Vec<u32> {
+    let mut widths = vec![default_width(pixels)];
+    widths.extend((4..=12).map(|log| 1 << log));
+    widths.extend(
+        common_line_lengths(data)
+            .into_iter()
+            .filter(|&length| length % stride as u32 == 0)
+            .map(|length| length / stride as u32),
+    );
+    widths.retain(|&width| {
+        (1..=MAX_DIMENSION).contains(&width) && pixels.div_ceil(width) <= MAX_DIMENSION
+    });
+    widths.sort();
+    widths.dedup();
+    widths
+}
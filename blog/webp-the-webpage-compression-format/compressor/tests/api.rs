@@ -0,0 +1,270 @@
+use compressor::config::{Encoder, EncoderConfig};
+use compressor::layout::Layout;
+use compressor::segment::{self, Kind};
+use compressor::transform::{self, Geometry, Transform, BLOCK_ROWS};
+use compressor::width::{Width, STREAM_TILE_PIXELS};
+use compressor::{compress, compress_stream, decompress, Error, Options};
+use std::io::Cursor;
+
+#[test]
+fn library_roundtrip() {
+    let data = b"<p>Hello from the library</p>\n".repeat(50);
+    let compressed = compress(&data, &Options::default()).unwrap();
+    assert_eq!(compressed.tiles.len(), 1);
+    assert_eq!(decompress(&compressed.to_bytes()).unwrap(), data);
+
+    let options = Options {
+        width: Some(Width::Fixed(1)),
+        transforms: vec![Transform::Bwt, Transform::Mtf],
+        ..Options::default()
+    };
+    let compressed = compress(&data, &options).unwrap();
+    assert_eq!(decompress(&compressed.to_bytes()).unwrap(), data);
+    assert_eq!(compressed.settings[0].width, 1);
+
+    // What was picked is reported rather than printed
+    let options = Options {
+        width: Some(Width::Auto),
+        max_effort: true,
+        ..Options::default()
+    };
+    let compressed = compress(&data, &options).unwrap();
+    assert_eq!(compressed.settings.len(), 1);
+    let settings = compressed.settings[0];
+    let picked = Options {
+        width: Some(Width::Fixed(settings.width)),
+        config: settings.config,
+        ..Options::default()
+    };
+    assert_eq!(
+        compress(&data, &picked).unwrap().to_bytes(),
+        compressed.to_bytes()
+    );
+}
+
+#[test]
+fn library_errors() {
+    assert!(matches!(
+        compress(b"", &Options::default()),
+        Err(Error::EmptyInput)
+    ));
+    for width in [0, 20000] {
+        let options = Options {
+            width: Some(Width::Fixed(width)),
+            ..Options::default()
+        };
+        assert!(matches!(
+            compress(b"data", &options),
+            Err(Error::DimensionOverflow { width: w, .. }) if w == width
+        ));
+    }
+    let options = Options {
+        transforms: vec![Transform::Delta; 5],
+        ..Options::default()
+    };
+    assert!(matches!(
+        compress(b"data", &options),
+        Err(Error::TooManyTransforms(5))
+    ));
+
+    assert!(matches!(decompress(b""), Err(Error::UnknownFormat)));
+    assert!(matches!(
+        decompress(b"not a webp"),
+        Err(Error::UnknownFormat)
+    ));
+    let mut webp = compress(b"data", &Options::default()).unwrap().to_bytes();
+    let length = webp.len();
+    webp[length - 4..].fill(0xff);
+    assert!(decompress(&webp).is_err());
+}
+
+#[test]
+fn library_backends() {
+    let data: Vec<u8> = (0..5000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 28) as u8 + b'a')
+        .collect();
+    for encoder in [
+        Encoder::Libwebp,
+        Encoder::Native,
+        Encoder::Png,
+        Encoder::Qoi,
+    ] {
+        let options = Options {
+            config: EncoderConfig {
+                encoder,
+                ..EncoderConfig::default()
+            },
+            ..Options::default()
+        };
+        let compressed = compress(&data, &options).unwrap();
+        assert_eq!(
+            decompress(&compressed.to_bytes()).unwrap(),
+            data,
+            "{encoder:?}"
+        );
+        assert_eq!(
+            compressor::embed::check_browser_support(&options).is_ok(),
+            encoder != Encoder::Qoi
+        );
+    }
+}
+
+#[test]
+fn library_corrupted_dimensions() {
+    let options = Options {
+        config: EncoderConfig {
+            encoder: Encoder::Qoi,
+            ..EncoderConfig::default()
+        },
+        ..Options::default()
+    };
+    let qoi = compress(b"data", &options).unwrap().to_bytes();
+    // Too large for the encoder, too large for the data, and the largest the header can hold
+    for (width, height) in [(20000, 1), (16383, 16383), (u32::MAX, u32::MAX)] {
+        let mut corrupted = qoi.clone();
+        corrupted[4..8].copy_from_slice(&width.to_be_bytes());
+        corrupted[8..12].copy_from_slice(&height.to_be_bytes());
+        assert!(matches!(decompress(&corrupted), Err(Error::Decode)));
+    }
+}
+
+#[test]
+fn library_stream() {
+    let data = b"<li>Streamed one tile at a time</li>\n".repeat(2000);
+    // Starts in the middle, like a file that was partly read already
+    let mut input = Cursor::new([b"skipped".as_slice(), &data].concat());
+    for layout in [Layout::Grayscale, Layout::Planes] {
+        // A width of 1 makes tiles of 16 KiB at most, or 48 KiB with planes
+        let options = Options {
+            width: Some(Width::Fixed(1)),
+            layout,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        input.set_position(7);
+        let settings = compress_stream(&mut input, &mut output, &options).unwrap();
+        let compressed = compress(&data, &options).unwrap();
+        assert!(compressed.tiles.len() > 1);
+        assert_eq!(settings.len(), compressed.tiles.len());
+        assert_eq!(output, compressed.to_bytes(), "{layout:?}");
+        assert_eq!(decompress(&output).unwrap(), data);
+    }
+
+    // Streamed tiles are smaller, so that memory use stays bounded
+    let data = b"<li>Streamed in small tiles</li>\n".repeat(STREAM_TILE_PIXELS as usize / 20);
+    let mut output = Vec::new();
+    let settings = compress_stream(&mut Cursor::new(&data), &mut output, &Options::default());
+    assert_eq!(settings.unwrap().len(), 2);
+    assert_eq!(compress(&data, &Options::default()).unwrap().tiles.len(), 1);
+    assert_eq!(decompress(&output).unwrap(), data);
+
+    let options = Options {
+        transforms: vec![Transform::Bwt],
+        ..Options::default()
+    };
+    assert!(matches!(
+        compress_stream(&mut Cursor::new(&data), &mut Vec::new(), &options),
+        Err(Error::StreamedTransforms)
+    ));
+    assert!(matches!(
+        compress_stream(&mut Cursor::new(b""), &mut Vec::new(), &Options::default()),
+        Err(Error::EmptyInput)
+    ));
+}
+
+#[test]
+fn library_segments() {
+    let markup = "<p>Some text with <a href=#>a link</a>".repeat(20);
+    let svg = format!(
+        "<svg viewbox=\"0 0 10 10\">{}<svg><path d=M0,0 /></svg></svg>",
+        "<path d=\"M1.5 2.5 L3 4\"/>".repeat(50)
+    );
+    let script = "let x = [1, 2, 3].map(y => y * 2);\n".repeat(30);
+    let html =
+        format!("{markup}{svg}{markup}<script>{script}</script><style>p{{}}</style>{markup}");
+    let segments = segment::segments(html.as_bytes(), 100);
+    let kinds: Vec<Kind> = segments.iter().map(|(kind, _)| *kind).collect();
+    // The style is too short to be a segment of its own
+    assert_eq!(
+        kinds,
+        [
+            Kind::Markup,
+            Kind::Svg,
+            Kind::Markup,
+            Kind::Script,
+            Kind::Markup
+        ]
+    );
+    assert_eq!(&html[segments[1].1.clone()], svg);
+    // Short segments are merged into the previous one
+    assert!(html[segments[3].1.clone()].starts_with(&script));
+    assert!(html[segments[3].1.clone()].ends_with("</script><style>p{}"));
+    assert_eq!(segments.last().unwrap().1.end, html.len());
+
+    // Each segment starts on a row of blocks, counting the bytes before the data
+    let geometry = Geometry {
+        row_len: 10,
+        offset: 21,
+    };
+    let aligned = transform::apply(&[Transform::SegmentAlign], html.as_bytes(), geometry).unwrap();
+    for (_, range) in &segments {
+        let start = aligned
+            .windows(range.len())
+            .position(|window| window == &html.as_bytes()[range.clone()])
+            .unwrap();
+        assert_eq!(
+            (start + geometry.offset) % (geometry.row_len * BLOCK_ROWS),
+            0
+        );
+    }
+    assert_eq!(
+        transform::invert(&[Transform::SegmentAlign], aligned).unwrap(),
+        html.as_bytes()
+    );
+
+    let options = Options {
+        transforms: vec![Transform::SegmentAlign],
+        ..Options::default()
+    };
+    let compressed = compress(html.as_bytes(), &options).unwrap();
+    assert_eq!(decompress(&compressed.to_bytes()).unwrap(), html.as_bytes());
+}
+
+#[test]
+fn library_lines() {
+    let text =
+        b"fn main() {\n    println!(\"Hello\");\n}\n\nsome line that is too long for one row\n";
+    // Each line starts on a row of the image, counting the bytes before the data
+    let geometry = Geometry {
+        row_len: 16,
+        offset: 21,
+    };
+    let aligned = transform::apply(&[Transform::LineAlign], text, geometry).unwrap();
+    let mut start = 0;
+    for line in text.split_inclusive(|&b| b == b'\n') {
+        let offset = aligned[start..]
+            .windows(line.len())
+            .position(|window| window == line)
+            .unwrap();
+        start += offset;
+        assert_eq!((start + geometry.offset) % geometry.row_len, 0, "{line:?}");
+        start += line.len();
+    }
+    assert_eq!(
+        transform::invert(&[Transform::LineAlign], aligned).unwrap(),
+        text
+    );
+
+    assert!(matches!(
+        transform::apply(&[Transform::LineAlign], text, Geometry::default()),
+        Err(Error::RowLength(0))
+    ));
+    let geometry = Geometry {
+        row_len: 1 << 16,
+        offset: 0,
+    };
+    assert!(matches!(
+        transform::apply(&[Transform::SegmentAlign], text, geometry),
+        Err(Error::RowLength(65536))
+    ));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{corpus, corpus_dir, run, temp_dir};
+
+#[test]
+fn bench_reports_every_file_and_codec() {
+    let files = corpus().len();
+    let corpus = corpus_dir();
+
+    let csv = String::from_utf8(run(
+        &["bench", corpus.to_str().unwrap(), "--format", "csv"],
+        b"",
+    ))
+    .unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("file,codec,raw,compressed,ratio,encode_ms,decode_ms")
+    );
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows.len(), files * 8);
+    for codec in [
+        "gzip", "zopfli", "brotli", "bzip2", "webp", "vp8l", "png", "qoi",
+    ] {
+        assert_eq!(rows.iter().filter(|row| row[1] == codec).count(), files);
+    }
+
+    let json = String::from_utf8(run(
+        &["bench", corpus.to_str().unwrap(), "--format", "json"],
+        b"",
+    ))
+    .unwrap();
+    assert!(json.starts_with("[\n") && json.ends_with("]\n"));
+    assert_eq!(json.matches(r#""codec":"webp""#).count(), files);
+}
+
+#[test]
+fn bench_skips_failed_measurements() {
+    let corpus = temp_dir("bench");
+    // The compressor rejects empty input, the general-purpose formats don't
+    std::fs::write(corpus.join("empty.txt"), "").unwrap();
+    std::fs::write(corpus.join("text.txt"), "Some text. ".repeat(100)).unwrap();
+
+    let csv = String::from_utf8(run(
+        &["bench", corpus.to_str().unwrap(), "--format", "csv"],
+        b"",
+    ))
+    .unwrap();
+    let rows: Vec<Vec<&str>> = csv
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').collect())
+        .collect();
+    let codecs = |file| {
+        rows.iter()
+            .filter(|row| row[0] == file)
+            .map(|row| row[1])
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(codecs("empty.txt"), ["gzip", "zopfli", "brotli", "bzip2"]);
+    assert_eq!(codecs("text.txt").len(), 8);
+
+    let table = String::from_utf8(run(&["bench", corpus.to_str().unwrap()], b"")).unwrap();
+    let rows = |file| {
+        table
+            .lines()
+            .filter(|line| line.contains(file))
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+    // Size, ratio and both timings are missing for the codecs that failed
+    let missing = |rows: &[Vec<&str>]| rows.iter().filter(|row| row[3..] == ["-"; 4]).count();
+    assert_eq!(rows("empty.txt").len(), 8);
+    assert_eq!(missing(&rows("empty.txt")), 4);
+    assert_eq!(missing(&rows("text.txt")), 0);
+    for row in rows("text.txt") {
+        assert_eq!(row.len(), 7);
+        let [raw, size, ratio, encode, decode] =
+            [2, 3, 4, 5, 6].map(|i| row[i].parse::<f64>().unwrap());
+        assert!((ratio - size / raw).abs() < 0.001, "{row:?}");
+        assert!(encode >= 0.0 && decode >= 0.0);
+    }
+    std::fs::remove_dir_all(corpus).unwrap();
+}
@@ -0,0 +1,60 @@
+// Helpers shared by the integration tests. Each test file uses only some of them.
+#![allow(dead_code)]
+
+use base64::Engine;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("compressor-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../corpus")
+}
+
+// The files in the corpus, sorted
+pub fn corpus() -> Vec<PathBuf> {
+    let mut entries: Vec<_> = std::fs::read_dir(corpus_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty());
+    entries
+}
+
+// Extracts the images from the data URLs in the script of an embedded page
+pub fn embedded_webps(page: &str) -> Vec<u8> {
+    page.split("`data:image/webp;base64,")
+        .skip(1)
+        .flat_map(|rest| {
+            base64::engine::general_purpose::STANDARD
+                .decode(rest.split('`').next().unwrap())
+                .unwrap()
+        })
+        .collect()
+}
+
+// Runs the compressor binary and returns its output
+
+pub fn run(args: &[&str], input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_compressor"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to start compressor");
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // Write from another thread so that a full stdout pipe can't deadlock us
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "compressor {args:?} failed");
+    output.stdout
+}
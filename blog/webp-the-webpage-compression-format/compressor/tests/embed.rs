@@ -0,0 +1,340 @@
+mod common;
+
+use common::{embedded_webps, run, temp_dir};
+use compressor::config::{Encoder, EncoderConfig};
+use compressor::embed::{self, embed_file, embed_page};
+use compressor::encoding::{Encoding, BASE122_ILLEGAL, BASE85_ALPHABET};
+use compressor::header::{CHECKSUM_OFFSET, HEADER_LEN, LENGTH_OFFSET};
+use compressor::layout::Layout;
+use compressor::{compress, Error, Options};
+use std::path::Path;
+
+const PAGE: &str = "<!doctypehtml><title>Test</title><h1>Above the fold</h1><cut></cut><p>Below the fold, with UTF-8: \u{2014}</p>";
+
+// The bootstrap's expression for a 32-bit header field, given how it reads the byte at an offset
+fn header_u32(start: usize, read: impl Fn(usize) -> String) -> String {
+    (0..4)
+        .map(|i| match i {
+            0 => read(start * 4),
+            _ => format!("{}<<{}", read((start + i) * 4), 8 * i),
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn embed(dir: &Path, args: &[&str]) -> String {
+    let input = dir.join("index.html");
+    std::fs::write(&input, PAGE).unwrap();
+    let mut full_args = vec!["embed", input.to_str().unwrap()];
+    full_args.extend(args);
+    run(&full_args, b"");
+    std::fs::read_to_string(input).unwrap()
+}
+
+#[test]
+fn embeds_page() {
+    let dir = temp_dir("embed");
+    let page = embed(&dir, &[]);
+
+    assert_eq!(
+        std::fs::read_to_string(dir.join("nojs.html")).unwrap(),
+        PAGE
+    );
+    let (before, _) = PAGE.split_once("<cut></cut>").unwrap();
+    assert!(page.starts_with(before));
+    assert!(page.contains("<noscript><meta http-equiv=refresh content=0;url=nojs.html></noscript>"));
+    assert!(page.contains(r#"location.href="nojs.html""#));
+    assert!(page.contains(&format!("y[i]=p[i*4+{}]", HEADER_LEN * 4)));
+    // The checksum is verified before the page is replaced
+    let checksum = header_u32(CHECKSUM_OFFSET, |o| format!("p[{o}]"));
+    assert!(page.contains(&format!("e={checksum}\n")));
+    assert!(page.find("if(~z!=e)throw 0").unwrap() < page.find("innerHTML=").unwrap());
+    assert!(page.ends_with("</script>"));
+
+    assert_eq!(
+        run(&["decompress"], &embedded_webps(&page)),
+        PAGE.as_bytes()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn refuses_embedded_page() {
+    let dir = temp_dir("embed-twice");
+    let page = embed(&dir, &[]);
+    let input = dir.join("index.html");
+    let error = embed_file(
+        &input,
+        None,
+        None,
+        false,
+        1,
+        Encoding::Base64,
+        &Options::default(),
+    );
+    assert!(matches!(error, Err(Error::AlreadyEmbedded(path)) if path == input));
+    // The fallback still holds the original
+    assert_eq!(std::fs::read_to_string(&input).unwrap(), page);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("nojs.html")).unwrap(),
+        PAGE
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn refuses_unembeddable_options() {
+    let options = Options {
+        config: EncoderConfig {
+            encoder: Encoder::Qoi,
+            ..EncoderConfig::default()
+        },
+        ..Options::default()
+    };
+    let error = embed_page(
+        PAGE,
+        "nojs.html",
+        "index.webp",
+        false,
+        1,
+        Encoding::Base64,
+        &options,
+    );
+    assert!(matches!(
+        error,
+        Err(Error::UnsupportedByBrowsers(Encoder::Qoi))
+    ));
+
+    let compressed = [compress(PAGE.as_bytes(), &Options::default()).unwrap()];
+    let options = |encoding, external_url| embed::Options {
+        layout: Layout::Grayscale,
+        transforms: &[],
+        repair: false,
+        nojs_url: "nojs.html",
+        dictionary_url: None,
+        encoding,
+        external_url,
+    };
+    assert!(matches!(
+        embed::embed(
+            PAGE,
+            &compressed,
+            &options(Encoding::Auto, Some("index.webp"))
+        ),
+        Err(Error::UnresolvedEncoding)
+    ));
+    assert!(matches!(
+        embed::embed(PAGE, &compressed, &options(Encoding::External, None)),
+        Err(Error::MissingExternalUrl)
+    ));
+    assert!(embed::embed(
+        PAGE,
+        &compressed,
+        &options(Encoding::External, Some("index.webp"))
+    )
+    .is_ok());
+}
+
+#[test]
+fn embeds_page_without_doctype() {
+    // Neither a cut nor a doctype, and not ASCII where the doctype would end
+    let html = "ééééé<p>Text";
+    let embedded = embed_page(
+        html,
+        "nojs.html",
+        "index.webp",
+        false,
+        1,
+        Encoding::Base64,
+        &Options::default(),
+    )
+    .unwrap();
+    assert!(embedded.page.starts_with("<noscript>"));
+}
+
+#[test]
+fn embeds_tiles_and_layouts() {
+    let dir = temp_dir("embed-tiles");
+    for layout in ["grayscale", "rgb", "rgba", "planes"] {
+        // A width of 1 forces several tiles for anything longer than 16 KiB
+        let page = embed(&dir, &["--layout", layout, "--width", "1"]);
+        assert_eq!(page.matches("data:image/webp").count(), 1, "{layout}");
+        assert_eq!(
+            run(&["decompress"], &embedded_webps(&page)),
+            PAGE.as_bytes()
+        );
+    }
+
+    let long_page = PAGE.repeat(1000);
+    let input = dir.join("long.html");
+    std::fs::write(&input, &long_page).unwrap();
+    let output = dir.join("out.html");
+    run(
+        &[
+            "embed",
+            input.to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+            "--nojs",
+            dir.join("fallback.html").to_str().unwrap(),
+            "--width",
+            "1",
+        ],
+        b"",
+    );
+    let page = std::fs::read_to_string(output).unwrap();
+    assert!(page.matches("data:image/webp").count() > 1);
+    assert!(page.contains(r#"location.href="fallback.html""#));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("fallback.html")).unwrap(),
+        long_page
+    );
+    assert_eq!(
+        run(&["decompress"], &embedded_webps(&page)),
+        long_page.as_bytes()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn embeds_transforms() {
+    let dir = temp_dir("embed-transforms");
+    let page = embed(&dir, &["--transform", "bwt,mtf"]);
+    // Tiles are concatenated before the transforms are inverted, MTF first
+    assert!(page.contains("new Blob(d)"));
+    let mtf = page.find("m.unshift").unwrap();
+    let bwt = page.find("T[i]=C[b[i]]++").unwrap();
+    assert!(mtf < bwt);
+    assert_eq!(
+        run(&["decompress"], &embedded_webps(&page)),
+        PAGE.as_bytes()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn embeds_repair() {
+    let dir = temp_dir("embed-repair");
+    let page = embed(&dir, &["--repair"]);
+    assert!(page.contains(&format!("y[i]=g(i*4+{})", HEADER_LEN * 4)));
+    let length = header_u32(LENGTH_OFFSET, |o| format!("g({o})"));
+    assert!(page.contains(&format!("y=new Uint8Array({length})")));
+    let checksum = header_u32(CHECKSUM_OFFSET, |o| format!("g({o})"));
+    assert!(page.contains(&format!("e={checksum}\n")));
+    assert_eq!(
+        run(&["decompress"], &embedded_webps(&page)),
+        PAGE.as_bytes()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn refuses_repair_without_grayscale() {
+    let dir = temp_dir("embed-repair-rgb");
+    let input = dir.join("index.html");
+    std::fs::write(&input, PAGE).unwrap();
+    let options = Options {
+        layout: Layout::Rgb,
+        ..Options::default()
+    };
+    let error = embed_file(&input, None, None, true, 1, Encoding::Base64, &options);
+    assert!(matches!(error, Err(Error::UnsupportedRepair(Layout::Rgb))));
+    assert_eq!(std::fs::read_to_string(&input).unwrap(), PAGE);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn embeds_chunks() {
+    let dir = temp_dir("embed-chunks");
+    let paragraphs: String = (0..100)
+        .map(|i| format!("<p>Paragraph {i} of the post"))
+        .collect();
+    let html = format!("<!doctypehtml><title>Test</title><h1>Above the fold</h1><cut></cut><div>{paragraphs}</div><footer>The end</footer>");
+    let input = dir.join("index.html");
+    std::fs::write(&input, &html).unwrap();
+    run(&["embed", input.to_str().unwrap(), "--chunks", "4"], b"");
+    let page = std::fs::read_to_string(input).unwrap();
+
+    let (_, chunks) = page.split_once("for(let U of[").unwrap();
+    let (chunks, _) = chunks.split_once("]){let s=await r(U)").unwrap();
+    let chunks: Vec<String> = chunks
+        .split("],[")
+        .map(|urls| String::from_utf8(run(&["decompress"], &embedded_webps(urls))).unwrap())
+        .collect();
+    assert_eq!(chunks.len(), 4);
+    // The skeleton keeps the footer, and the paragraphs are inserted before the marker in order
+    let marker = "<template id=wpc-rest></template>";
+    assert!(chunks[0].contains(&format!("<div>{marker}</div><footer>")));
+    assert!(chunks[1..].iter().all(|chunk| chunk.starts_with("<p>")));
+    assert_eq!(chunks[0].replace(marker, &chunks[1..].concat()), html);
+    // Later chunks are larger
+    assert!(chunks[1].len() < chunks[3].len());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn base85_decode(text: &str) -> Vec<u8> {
+    let mut output = Vec::new();
+    for group in text.as_bytes().chunks(5) {
+        let n = (0..5).fold(0u64, |n, i| {
+            let digit = group.get(i).map_or(84, |&c| {
+                BASE85_ALPHABET.bytes().position(|a| a == c).unwrap() as u64
+            });
+            n * 85 + digit
+        });
+        output.extend(&(n as u32).to_be_bytes()[..group.len() - 1]);
+    }
+    output
+}
+
+fn base122_decode(text: &str) -> Vec<u8> {
+    let mut bits = Vec::new();
+    let mut push = |value: u32| bits.extend((0..7).rev().map(|i| value >> i & 1));
+    for c in text.chars().map(u32::from) {
+        if c < 128 {
+            push(c);
+            continue;
+        }
+        if let Some(&illegal) = BASE122_ILLEGAL.get((c >> 8 & 7) as usize) {
+            push(illegal as u32);
+        }
+        push(c & 127);
+    }
+    bits.chunks_exact(8)
+        .map(|byte| byte.iter().fold(0, |n, &bit| n << 1 | bit as u8))
+        .collect()
+}
+
+#[test]
+fn embeds_encodings() {
+    let dir = temp_dir("embed-encodings");
+    let page = embed(&dir, &["--encoding", "base64"]);
+    let data_url = embedded_webps(&page);
+    assert!(data_url.len() > 100);
+
+    for (encoding, decode) in [
+        ("base85", base85_decode as fn(&str) -> Vec<u8>),
+        ("base122", base122_decode),
+    ] {
+        let page = embed(&dir, &["--encoding", encoding]);
+        assert!(!page.contains("data:image/webp"), "{encoding}");
+        let (_, images) = page.split_once("for(let U of[[`").unwrap();
+        let (image, _) = images.split_once("`]]").unwrap();
+        // Nothing that would end the template literal or the script
+        assert!(
+            !image.contains(['`', '\\', '$', '<', '\r', '\0']),
+            "{encoding}"
+        );
+        assert_eq!(decode(image), data_url, "{encoding}");
+    }
+
+    let page = embed(&dir, &["--encoding", "external"]);
+    assert!(page.contains("<link rel=preload href=index.webp as=fetch crossorigin>"));
+    assert!(page.contains("for(let U of[[[0,"));
+    let images = std::fs::read(dir.join("index.webp")).unwrap();
+    assert_eq!(run(&["decompress"], &images), PAGE.as_bytes());
+
+    // Whatever wins, the page decodes
+    let page = embed(&dir, &["--encoding", "auto"]);
+    assert!(page.contains("data:image/webp") || page.contains("rel=preload"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,54 @@
+use compressor::html::{Token, Tokenizer};
+
+fn tokens(html: &str) -> Vec<(&str, Token<'_>)> {
+    Tokenizer::new(html)
+        .map(|(range, token)| (&html[range], token))
+        .collect()
+}
+
+#[test]
+fn raw_text_ends_at_its_end_tag_in_any_case() {
+    let html = "<SCRIPT>if(a<b)x='</p>'</SCRIPT><p>After<Style>p{}</style>";
+    assert_eq!(
+        tokens(html),
+        [
+            (
+                "<SCRIPT>",
+                Token::Start {
+                    name: "SCRIPT",
+                    self_closing: false
+                }
+            ),
+            ("if(a<b)x='</p>'", Token::Text),
+            ("</SCRIPT>", Token::End { name: "SCRIPT" }),
+            (
+                "<p>",
+                Token::Start {
+                    name: "p",
+                    self_closing: false
+                }
+            ),
+            ("After", Token::Text),
+            (
+                "<Style>",
+                Token::Start {
+                    name: "Style",
+                    self_closing: false
+                }
+            ),
+            ("p{}", Token::Text),
+            ("</style>", Token::End { name: "style" }),
+        ]
+    );
+}
+
+#[test]
+fn svg_title_is_not_raw_text() {
+    let html = "<svg><title>A <b>bold</b> title</title></svg><title><b></title>";
+    let text: Vec<&str> = tokens(html)
+        .into_iter()
+        .filter(|(_, token)| *token == Token::Text)
+        .map(|(text, _)| text)
+        .collect();
+    assert_eq!(text, ["A ", "bold", " title", "<b>"]);
+}
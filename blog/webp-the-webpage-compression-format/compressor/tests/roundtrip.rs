@@ -0,0 +1,98 @@
+mod common;
+
+use common::{corpus, run};
+
+#[test]
+fn corpus_roundtrip() {
+    for path in corpus() {
+        let original = std::fs::read(&path).unwrap();
+        for args in [
+            &["compress"][..],
+            &["--width", "auto"],
+            &["--width", "7"],
+            &["--layout", "rgb"],
+            &["--layout", "rgba", "--width", "5"],
+            &["--layout", "planes"],
+            &["--encoder", "native"],
+            &["--encoder", "native", "--layout", "rgba", "--width", "5"],
+            &["--encoder", "png"],
+            &["--encoder", "png", "--layout", "rgb", "--method", "6"],
+            &["--encoder", "png", "--layout", "rgba", "--width", "5"],
+            &["--encoder", "qoi"],
+            &["--encoder", "qoi", "--layout", "rgba", "--width", "5"],
+            &["--max-effort", "--method", "0", "--image-hint", "graph"],
+            &["--transform", "bwt,mtf"],
+            &["--transform", "delta", "--layout", "rgb"],
+            &["--transform", "line-align"],
+            &["--transform", "line-align,delta", "--width", "auto"],
+            &["--transform", "segment-align"],
+            &[
+                "--transform",
+                "segment-align",
+                "--layout",
+                "rgb",
+                "--width",
+                "auto",
+            ],
+            &[
+                "--transform",
+                "bwt",
+                "--transform",
+                "line-align",
+                "--width",
+                "3",
+            ],
+        ] {
+            let webp = run(args, &original);
+            let decompressed = run(&["decompress"], &webp);
+            assert!(
+                decompressed == original,
+                "{} did not round-trip with {args:?}",
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn tiles_roundtrip() {
+    // With a width of 1, a tile holds at most 16383 pixels, so this needs several tiles
+    let original: Vec<u8> = (0..100_000u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+        .collect();
+    for layout in ["grayscale", "rgb", "planes"] {
+        let webps = run(&["--width", "1", "--layout", layout], &original);
+        assert!(webps.windows(4).filter(|window| window == b"RIFF").count() > 1);
+        assert!(run(&["decompress"], &webps) == original, "{layout}");
+    }
+    for encoder in ["png", "qoi"] {
+        let images = run(&["--width", "1", "--encoder", encoder], &original);
+        assert!(run(&["decompress"], &images) == original, "{encoder}");
+    }
+}
+
+#[test]
+fn native_encoder_roundtrip() {
+    // Markup with repeated structure and a noisy tail, to exercise backward references, the color
+    // cache and several prefix code groups
+    let mut original = Vec::new();
+    for i in 0..200 {
+        original.extend(
+            format!(
+                "<li><a href=\"/post-{i}/\">Post number {}</a></li>\n",
+                i * 7919 % 1000
+            )
+            .bytes(),
+        );
+    }
+    original.extend((0..2000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8));
+    for args in [
+        &["--encoder", "native"][..],
+        &["--encoder", "native", "--method", "0", "--layout", "rgb"],
+        &["--encoder", "native", "--method", "6", "--layout", "rgba"],
+        &["--encoder", "native", "--layout", "planes", "--width", "64"],
+    ] {
+        let webp = run(args, &original);
+        assert!(run(&["decompress"], &webp) == original, "{args:?}");
+    }
+}
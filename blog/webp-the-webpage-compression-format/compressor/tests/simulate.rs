@@ -0,0 +1,286 @@
+mod common;
+
+use common::corpus;
+use compressor::dictionary::Dictionary;
+use compressor::embed::{self, Options};
+use compressor::encoding::{base64, Encoding};
+use compressor::layout::Layout;
+use compressor::transform::Transform;
+use compressor::width::Width;
+use compressor::{backend, compress, progressive, simulate};
+use std::process::Command;
+
+fn options<'a>(args: &'a compressor::Options, repair: bool) -> Options<'a> {
+    Options {
+        layout: args.layout,
+        transforms: &args.transforms,
+        repair,
+        nojs_url: "nojs.html",
+        dictionary_url: None,
+        encoding: Encoding::Base64,
+        external_url: None,
+    }
+}
+
+#[test]
+fn corpus_decodes_like_the_bootstrap() {
+    let variants = [
+        (Layout::Grayscale, vec![], false),
+        (Layout::Grayscale, vec![], true),
+        (Layout::Rgb, vec![], false),
+        (Layout::Rgba, vec![], false),
+        (Layout::Planes, vec![], false),
+        (
+            Layout::Grayscale,
+            vec![Transform::Bwt, Transform::Mtf],
+            false,
+        ),
+        (
+            Layout::Grayscale,
+            vec![Transform::LineAlign, Transform::Delta],
+            true,
+        ),
+        (Layout::Rgb, vec![Transform::SegmentAlign], false),
+    ];
+    for path in corpus() {
+        let original = std::fs::read(&path).unwrap();
+        for (layout, transforms, repair) in &variants {
+            let args = compressor::Options {
+                layout: *layout,
+                transforms: transforms.clone(),
+                ..compressor::Options::default()
+            };
+            let compressed = compress(&original, &args).unwrap();
+            let decoded = simulate::decode_chunk(&compressed.tiles, None, &options(&args, *repair));
+            assert!(
+                matches!(decoded.as_deref(), Ok(data) if data == original),
+                "{} is decoded wrongly with {layout:?}, {transforms:?}",
+                path.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn tiles_and_chunks_decode_like_the_bootstrap() {
+    let paragraphs: String = (0..6000)
+        .map(|i| format!("<p>Paragraph {i}, with UTF-8: \u{2014}"))
+        .collect();
+    let html = format!("<!doctypehtml><h1>Above the fold</h1><cut></cut><div>{paragraphs}</div>");
+    // A width of 1 makes tiles of 16 KiB at most, or 64 KiB with RGBA, which uses the alpha channel
+    for layout in [Layout::Grayscale, Layout::Rgba] {
+        let args = compressor::Options {
+            width: Some(Width::Fixed(1)),
+            layout,
+            ..compressor::Options::default()
+        };
+        let chunks: Vec<_> = progressive::split(&html, 3)
+            .iter()
+            .map(|chunk| compress(chunk.as_bytes(), &args).unwrap())
+            .collect();
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().any(|chunk| chunk.tiles.len() > 1));
+        let document = simulate::document(&chunks, None, &options(&args, false)).unwrap();
+        assert_eq!(document, html, "{layout:?}");
+    }
+}
+
+#[test]
+fn dictionary_decodes_like_the_bootstrap() {
+    let pages = [
+        "<h1>One</h1><footer>Shared footer</footer>",
+        "<h1>Two</h1><footer>Shared footer</footer>",
+    ];
+    let dictionary = Dictionary::learn(&pages.map(str::as_bytes), 2);
+    assert!(!dictionary.is_empty());
+    let args = compressor::Options::default();
+    let dictionary_image = compress(&dictionary.to_bytes(), &args).unwrap().to_bytes();
+    let options = Options {
+        dictionary_url: Some("dictionary.webp"),
+        ..options(&args, false)
+    };
+    for page in pages {
+        let compressed = compress(&dictionary.substitute(page.as_bytes()), &args).unwrap();
+        let document =
+            simulate::document(&[compressed], Some(&dictionary_image), &options).unwrap();
+        assert_eq!(document, page);
+    }
+}
+
+// Stands in for the browser: fetch serves `files` and data URLs, createImageBitmap and WebGL return
+// what `simulate::read_pixels` computes for the image, and the document is just its HTML
+const BROWSER: &str = r#"const b64=s=>Buffer.from(s,"base64"),realFetch=fetch
+globalThis.fetch=async u=>u.startsWith("data:")?realFetch(u):new Response(b64(files[u]))
+globalThis.createImageBitmap=async(blob,o)=>{let i=images.get(Buffer.from(await blob.arrayBuffer()).toString("base64"))
+return{width:i.width,height:i.height,p:b64(o?.premultiplyAlpha=="none"?i.straight:i.premultiplied)}}
+let bitmap,html=""
+globalThis.OffscreenCanvas=class{getContext(){return{createTexture(){},bindTexture(){},texImage2D(...a){bitmap=a[5]},createFramebuffer(){},bindFramebuffer(){},framebufferTexture2D(){},readPixels(x,y,w,h,f,t,p){p.set(bitmap.p)}}}}
+globalThis.document={documentElement:{set innerHTML(s){html=s}},getElementById:()=>html.includes(marker)&&{insertAdjacentHTML(_,s){html=html.replace(marker,s+marker)},remove(){html=html.replace(marker,"")}}}
+globalThis.location={set href(u){console.error(`fell back to ${u}`);process.exit(1)}}
+"#;
+
+// Runs the bootstrap of `page` under Node.js and returns the document it produces, or None if Node.js
+// isn't installed
+fn run_bootstrap(
+    name: &str,
+    page: &str,
+    images: &[&[u8]],
+    files: &[(&str, &[u8])],
+) -> Option<String> {
+    Command::new("node").arg("--version").output().ok()?;
+    let image_entries: Vec<String> = images
+        .iter()
+        .map(|image| {
+            let (width, height) = backend::detect(image).unwrap().dimensions(image).unwrap();
+            format!(
+                r#"["{}",{{width:{width},height:{height},premultiplied:"{}",straight:"{}"}}]"#,
+                base64(image),
+                base64(&simulate::read_pixels(image, true).unwrap()),
+                base64(&simulate::read_pixels(image, false).unwrap()),
+            )
+        })
+        .collect();
+    let file_entries: Vec<String> = files
+        .iter()
+        .map(|(url, data)| format!(r#""{url}":"{}""#, base64(data)))
+        .collect();
+    let (_, script) = page.split_once("<script type=module>").unwrap();
+    let script = script.strip_suffix("</script>").unwrap();
+    let harness = format!(
+        "const images=new Map([{}]),files={{{}}},marker={:?}\n{BROWSER}{script}\nprocess.stdout.write(html)\n",
+        image_entries.join(","),
+        file_entries.join(","),
+        progressive::MARKER,
+    );
+    let path = std::env::temp_dir().join(format!(
+        "compressor-bootstrap-{name}-{}.mjs",
+        std::process::id()
+    ));
+    std::fs::write(&path, harness).unwrap();
+    let output = Command::new("node").arg(&path).output().unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert!(
+        output.status.success(),
+        "{name}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn bootstrap_runs_in_node() {
+    let paragraphs: String = (0..1500)
+        .map(|i| format!("<p>Paragraph {i}, with UTF-8: \u{2014}\n"))
+        .collect();
+    let html = format!(
+        "<!doctypehtml><h1>Above the fold</h1><cut></cut><style>p{{color:red}}</style>{paragraphs}<script>let x=1</script>"
+    );
+    let variants = [
+        (Layout::Grayscale, vec![], false, Encoding::Base64, 1, None),
+        (Layout::Grayscale, vec![], true, Encoding::Base85, 3, None),
+        (
+            Layout::Grayscale,
+            vec![],
+            false,
+            Encoding::Base64,
+            2,
+            Some(Width::Fixed(1)),
+        ),
+        (Layout::Rgb, vec![], false, Encoding::Base122, 1, None),
+        (Layout::Rgba, vec![], false, Encoding::External, 1, None),
+        (Layout::Planes, vec![], false, Encoding::Base64, 3, None),
+        (
+            Layout::Grayscale,
+            vec![Transform::Bwt, Transform::Mtf],
+            false,
+            Encoding::Base64,
+            1,
+            None,
+        ),
+        (
+            Layout::Grayscale,
+            vec![Transform::LineAlign, Transform::Delta],
+            true,
+            Encoding::Base64,
+            1,
+            None,
+        ),
+        (
+            Layout::Rgb,
+            vec![Transform::SegmentAlign],
+            false,
+            Encoding::External,
+            2,
+            None,
+        ),
+    ];
+    for (i, (layout, transforms, repair, encoding, chunks, width)) in
+        variants.into_iter().enumerate()
+    {
+        let args = compressor::Options {
+            width,
+            layout,
+            transforms: transforms.clone(),
+            ..compressor::Options::default()
+        };
+        let embedded = embed::embed_page(
+            &html,
+            "nojs.html",
+            "index.webp",
+            repair,
+            chunks,
+            encoding,
+            &args,
+        )
+        .unwrap();
+        let images: Vec<&[u8]> = embedded
+            .compressed
+            .iter()
+            .flat_map(|chunk| chunk.tiles.iter().map(Vec::as_slice))
+            .collect();
+        let external = embedded.external_file().unwrap_or_default();
+        let name = format!("variant-{i}");
+        let Some(document) =
+            run_bootstrap(&name, &embedded.page, &images, &[("index.webp", &external)])
+        else {
+            eprintln!("skipping: Node.js is not installed");
+            return;
+        };
+        assert!(
+            document == html,
+            "{layout:?}, {transforms:?}, repair {repair}, {encoding}, {chunks} chunks"
+        );
+    }
+}
+
+#[test]
+fn bootstrap_runs_in_node_with_dictionary() {
+    let pages = [
+        "<h1>One</h1><footer>Shared footer</footer>",
+        "<h1>Two</h1><footer>Shared footer</footer>",
+    ];
+    let dictionary = Dictionary::learn(&pages.map(str::as_bytes), 2);
+    let args = compressor::Options::default();
+    let dictionary_image = compress(&dictionary.to_bytes(), &args).unwrap().to_bytes();
+    let options = Options {
+        dictionary_url: Some("dictionary.webp"),
+        ..options(&args, false)
+    };
+    for (i, page) in pages.into_iter().enumerate() {
+        let compressed = compress(&dictionary.substitute(page.as_bytes()), &args).unwrap();
+        let embedded = embed::embed(page, std::slice::from_ref(&compressed), &options).unwrap();
+        let mut images = vec![dictionary_image.as_slice()];
+        images.extend(compressed.tiles.iter().map(Vec::as_slice));
+        let name = format!("dictionary-{i}");
+        let Some(document) = run_bootstrap(
+            &name,
+            &embedded,
+            &images,
+            &[("dictionary.webp", &dictionary_image)],
+        ) else {
+            eprintln!("skipping: Node.js is not installed");
+            return;
+        };
+        assert_eq!(document, page);
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{run, temp_dir};
+use compressor::{site, Error, Options};
+use std::path::Path;
+
+const HEAD: &str = "<!doctypehtml><html lang=en><meta charset=utf-8><link href=../all.css rel=stylesheet><header><nav><a href=../>Blog</a><a href=../feed.rss>RSS</a></nav></header>";
+
+fn page(i: usize) -> String {
+    // The escape byte has to survive the substitution too
+    format!("{HEAD}<h1>Post {i}</h1><p>Text \u{1} with a control character.</p><footer>The end</footer>")
+}
+
+#[test]
+fn site_shares_dictionary() {
+    let dir = temp_dir("site");
+    for i in 0..3 {
+        std::fs::create_dir_all(dir.join(format!("post-{i}"))).unwrap();
+        std::fs::write(dir.join(format!("post-{i}/index.html")), page(i)).unwrap();
+    }
+    std::fs::write(dir.join("index.html"), page(3)).unwrap();
+
+    let report = String::from_utf8(run(&["site", dir.to_str().unwrap(), "--write"], b"")).unwrap();
+    assert!(report.contains("post-0/index.html"));
+    assert!(report.contains("dictionary.webp"));
+    assert!(report.lines().any(|line| line.starts_with("total")));
+
+    let dictionary = run(
+        &["decompress"],
+        &std::fs::read(dir.join("dictionary.webp")).unwrap(),
+    );
+    let phrases: Vec<&[u8]> = dictionary.split(|&byte| byte == 0).collect();
+    assert!(phrases.contains(&&b"<link href=../all.css rel=stylesheet>"[..]));
+    assert!(phrases.contains(&&b"<footer>The end"[..]));
+    // Unique text stays in the pages
+    assert!(!phrases.iter().any(|phrase| phrase.starts_with(b"<h1>")));
+
+    for i in 0..3 {
+        let post = dir.join(format!("post-{i}"));
+        assert_eq!(
+            std::fs::read_to_string(post.join("nojs.html")).unwrap(),
+            page(i)
+        );
+        let embedded = std::fs::read_to_string(post.join("index.html")).unwrap();
+        // Without a cut, only the doctype is kept above the script
+        assert!(embedded.starts_with("<!doctypehtml><noscript>"));
+        assert!(embedded.contains("let D=await f(fetch(`../dictionary.webp`)"));
+    }
+    let root = std::fs::read_to_string(dir.join("index.html")).unwrap();
+    assert!(root.contains("let D=await f(fetch(`dictionary.webp`)"));
+
+    // Running again would learn the dictionary from the bootstrap and overwrite the originals
+    let error = site::run(
+        &dir,
+        Path::new("dictionary.webp"),
+        2,
+        true,
+        false,
+        &Options::default(),
+    );
+    assert!(matches!(error, Err(Error::AlreadyEmbedded(_))));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("post-0/nojs.html")).unwrap(),
+        page(0)
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{embedded_webps, temp_dir};
+use compressor::encoding::Encoding;
+use compressor::watch::Watcher;
+use compressor::{decompress, Options};
+use std::path::Path;
+
+fn page(title: &str) -> String {
+    format!(
+        "<!doctypehtml><h1>{title}</h1><cut></cut>{}",
+        "<p>Some text".repeat(100)
+    )
+}
+
+// The original stored in the images of an embedded page of one chunk
+fn original(path: &Path) -> String {
+    let webps = embedded_webps(&std::fs::read_to_string(path).unwrap());
+    String::from_utf8(decompress(&webps).unwrap()).unwrap()
+}
+
+#[test]
+fn watch_recompresses_changed_pages() {
+    let dir = temp_dir("watch");
+    for name in ["one", "two"] {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        std::fs::write(dir.join(name).join("index.html"), page(name)).unwrap();
+    }
+    let args = Options::default();
+    let mut watcher = Watcher::new(&dir, false, 1, Encoding::Base64, &args).unwrap();
+    assert!(watcher.poll().unwrap());
+    for name in ["one", "two"] {
+        let path = dir.join(name).join("index.html");
+        assert_eq!(
+            std::fs::read_to_string(path.with_file_name("nojs.html")).unwrap(),
+            page(name)
+        );
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with(&format!("<!doctypehtml><h1>{name}</h1><noscript>")));
+        assert_eq!(original(&path), page(name));
+    }
+    // Its own output is not a change
+    assert!(!watcher.poll().unwrap());
+
+    // The build regenerates every page, but only one of them changed
+    let one = std::fs::read_to_string(dir.join("one/index.html")).unwrap();
+    std::fs::write(dir.join("one/index.html"), page("one")).unwrap();
+    std::fs::write(dir.join("two/index.html"), page("two, edited")).unwrap();
+    assert!(watcher.poll().unwrap());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("one/index.html")).unwrap(),
+        one
+    );
+    assert_eq!(original(&dir.join("two/index.html")), page("two, edited"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("two/nojs.html")).unwrap(),
+        page("two, edited")
+    );
+
+    // Compressed pages are recognized after a restart
+    let two = std::fs::read_to_string(dir.join("two/index.html")).unwrap();
+    let mut watcher = Watcher::new(&dir, false, 1, Encoding::Base64, &args).unwrap();
+    assert!(!watcher.poll().unwrap());
+    std::fs::write(dir.join("two/index.html"), page("two, edited")).unwrap();
+    assert!(watcher.poll().unwrap());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("two/index.html")).unwrap(),
+        two
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn watch_retries_unreadable_pages() {
+    let dir = temp_dir("watch-unreadable");
+    std::fs::create_dir_all(dir.join("broken")).unwrap();
+    let path = dir.join("broken/index.html");
+    std::fs::write(&path, b"<!doctypehtml>\xff").unwrap();
+    let args = Options::default();
+    let mut watcher = Watcher::new(&dir, false, 1, Encoding::Base64, &args).unwrap();
+    assert!(!watcher.poll().unwrap());
+    assert!(!watcher.poll().unwrap());
+    // Fixed by the next build
+    std::fs::write(&path, page("fixed")).unwrap();
+    assert!(watcher.poll().unwrap());
+    assert_eq!(original(&path), page("fixed"));
+    std::fs::remove_dir_all(dir).unwrap();
+}